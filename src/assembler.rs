@@ -0,0 +1,1092 @@
+//Assembler for the Octo language, every emitted byte is mapped back to its source line.
+
+use crate::{EmuErr, Opcode, PG_START};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+};
+
+//Highest address XO-CHIP programs can be assembled to.
+const MEMORY_SIZE: usize = 0x10000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+//Bytes emitted for a single source line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLine {
+    pub addr: u16,
+    pub len: u16,
+    pub line: usize,
+}
+
+//Label addresses and the source line of every emitted byte.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceMap {
+    pub symbols: BTreeMap<String, u16>,
+    pub lines: Vec<SourceLine>,
+}
+
+impl SourceMap {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Source maps serialize")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    //Source line that emitted the byte at addr.
+    pub fn line_at(&self, addr: u16) -> Option<usize> {
+        self.lines
+            .iter()
+            .find(|line| (line.addr..line.addr.saturating_add(line.len)).contains(&addr))
+            .map(|line| line.line)
+    }
+}
+
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    pub source_map: SourceMap,
+}
+
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    Assembler::new(source).run()
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+//Addresses resolved once every label is known.
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    //Low 12 bits of the opcode at addr.
+    Nnn,
+    //16 bit word at addr, for i := long.
+    Long,
+    //:unpack, the opcodes at addr and addr + 2 load the label into v0 and v1.
+    Unpack { nibble: u8 },
+}
+
+struct Fixup {
+    addr: usize,
+    kind: FixupKind,
+    name: String,
+    line: usize,
+}
+
+enum Block {
+    //Address of the jump taken when the condition fails.
+    If {
+        jump: usize,
+        line: usize,
+    },
+    Else {
+        jump: usize,
+        line: usize,
+    },
+    Loop {
+        start: usize,
+        breaks: Vec<usize>,
+        line: usize,
+    },
+}
+
+//Right hand side of a comparison.
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Reg(usize),
+    Imm(u8),
+}
+
+//A test a single skip instruction can make.
+#[derive(Debug, Clone, Copy)]
+enum Test {
+    Eq(usize, Operand),
+    Ne(usize, Operand),
+    Key(usize),
+    NotKey(usize),
+}
+
+impl Test {
+    fn negate(self) -> Self {
+        match self {
+            Test::Eq(x, operand) => Test::Ne(x, operand),
+            Test::Ne(x, operand) => Test::Eq(x, operand),
+            Test::Key(x) => Test::NotKey(x),
+            Test::NotKey(x) => Test::Key(x),
+        }
+    }
+
+    //Opcode that skips the next instruction when the test holds.
+    fn skip_opcode(self) -> Opcode {
+        match self {
+            Test::Eq(x, Operand::Imm(kk)) => Opcode::SkipEqImm { x, kk },
+            Test::Ne(x, Operand::Imm(kk)) => Opcode::SkipNeImm { x, kk },
+            Test::Eq(x, Operand::Reg(y)) => Opcode::SkipEqReg { x, y },
+            Test::Ne(x, Operand::Reg(y)) => Opcode::SkipNeReg { x, y },
+            Test::Key(x) => Opcode::SkipKey { x },
+            Test::NotKey(x) => Opcode::SkipNotKey { x },
+        }
+    }
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    //The last token taken, for errors at the end of the source.
+    line: usize,
+    memory: Vec<u8>,
+    written: Vec<bool>,
+    here: usize,
+    end: usize,
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    lines: Vec<SourceLine>,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Self {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                let code = line.split('#').next().unwrap_or_default();
+                code.split_whitespace().map(move |text| Token {
+                    text: text.to_string(),
+                    line: index + 1,
+                })
+            })
+            .collect();
+        Self {
+            tokens,
+            line: 1,
+            memory: vec![0; MEMORY_SIZE],
+            written: vec![false; MEMORY_SIZE],
+            here: PG_START,
+            end: PG_START,
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<Assembly, AsmError> {
+        while let Some(token) = self.next_token() {
+            self.statement(token)?;
+        }
+        if let Some(block) = self.blocks.last() {
+            let (line, message) = match block {
+                Block::If { line, .. } | Block::Else { line, .. } => (*line, "if without end"),
+                Block::Loop { line, .. } => (*line, "loop without again"),
+            };
+            return Err(self.error_at(line, message));
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(addr) = self.labels.get(&fixup.name).copied() else {
+                return Err(self.error_at(fixup.line, &format!("Undefined name {}", fixup.name)));
+            };
+            match fixup.kind {
+                FixupKind::Nnn => {
+                    if addr > 0xFFF {
+                        return Err(self.error_at(
+                            fixup.line,
+                            &format!("{} is out of 12 bit range, use i := long", fixup.name),
+                        ));
+                    }
+                    self.memory[fixup.addr] |= (addr >> 8) as u8;
+                    self.memory[fixup.addr + 1] = addr as u8;
+                }
+                FixupKind::Long => {
+                    self.memory[fixup.addr..fixup.addr + 2].copy_from_slice(&addr.to_be_bytes());
+                }
+                FixupKind::Unpack { nibble } => {
+                    if addr > 0xFFF {
+                        return Err(self.error_at(
+                            fixup.line,
+                            &format!("{} is out of 12 bit range for :unpack", fixup.name),
+                        ));
+                    }
+                    self.memory[fixup.addr + 1] = nibble << 4 | (addr >> 8) as u8;
+                    self.memory[fixup.addr + 3] = addr as u8;
+                }
+            }
+        }
+        let symbols = self.labels.into_iter().collect();
+        Ok(Assembly {
+            rom: self.memory[PG_START..self.end].to_vec(),
+            source_map: SourceMap {
+                symbols,
+                lines: self.lines,
+            },
+        })
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.pop_front()?;
+        self.line = token.line;
+        Some(token)
+    }
+
+    fn expect_token(&mut self) -> Result<Token, AsmError> {
+        self.next_token()
+            .ok_or_else(|| self.error("Unexpected end of source"))
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.expect_token()?;
+        if token.text != text {
+            return Err(self.error(&format!("Expected {text}, found {}", token.text)));
+        }
+        Ok(())
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    fn error(&self, message: &str) -> AsmError {
+        self.error_at(self.line, message)
+    }
+
+    fn error_at(&self, line: usize, message: &str) -> AsmError {
+        AsmError {
+            line,
+            message: message.to_string(),
+        }
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        let text = token.text.as_str();
+        match text {
+            ":" => {
+                let name = self.expect_token()?.text;
+                self.define_label(&name)?;
+            }
+            ":const" => {
+                let name = self.expect_token()?.text;
+                let value = self.value()?;
+                self.consts.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.expect_token()?.text;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+            }
+            ":macro" => self.define_macro()?,
+            ":calc" => {
+                let name = self.expect_token()?.text;
+                let value = self.braced_expression()?;
+                self.consts.insert(name, value);
+            }
+            ":org" => {
+                let addr = self.integer(0, MEMORY_SIZE as i64 - 1)? as usize;
+                if addr < PG_START {
+                    return Err(self.error("Can't :org below the program start"));
+                }
+                self.here = addr;
+            }
+            ":byte" => {
+                let byte = if self.peek_is("{") {
+                    self.braced_expression()? as i64
+                } else {
+                    self.value()? as i64
+                };
+                self.emit_byte(to_byte(byte).ok_or_else(|| self.error("Byte out of range"))?)?;
+            }
+            ":call" => self.address_opcode(|nnn| Opcode::Call { nnn }.encode())?,
+            ":unpack" => {
+                let nibble = self.integer(0, 0xF)? as u8;
+                let name = self.expect_token()?.text;
+                let addr = self.here;
+                self.emit(Opcode::LoadImm { x: 0x0, kk: 0 })?;
+                self.emit(Opcode::LoadImm { x: 0x1, kk: 0 })?;
+                self.fixups.push(Fixup {
+                    addr,
+                    kind: FixupKind::Unpack { nibble },
+                    name,
+                    line: self.line,
+                });
+            }
+            "clear" => self.emit(Opcode::Clear)?,
+            "return" => self.emit(Opcode::Return)?,
+            "exit" => self.emit(Opcode::Exit)?,
+            "lores" => self.emit(Opcode::LowRes)?,
+            "hires" => self.emit(Opcode::HighRes)?,
+            "scroll-down" => {
+                let n = self.integer(0, 0xF)? as u8;
+                self.emit(Opcode::ScrollDown { n })?;
+            }
+            "scroll-up" => {
+                let n = self.integer(0, 0xF)? as u8;
+                self.emit(Opcode::ScrollUp { n })?;
+            }
+            "scroll-right" => self.emit(Opcode::ScrollRight)?,
+            "scroll-left" => self.emit(Opcode::ScrollLeft)?,
+            "audio" => self.emit(Opcode::Audio)?,
+            "plane" => {
+                let n = self.integer(0, 0x3)? as u8;
+                self.emit(Opcode::Plane { n })?;
+            }
+            "jump" => self.address_opcode(|nnn| Opcode::Jump { nnn }.encode())?,
+            "jump0" => self.address_opcode(|nnn| Opcode::JumpOffset { nnn }.encode())?,
+            //0NNN machine code routines aren't run by any interpreter so have no Opcode.
+            "native" => self.address_opcode(Ok)?,
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek_is("-") {
+                    self.next_token();
+                    let y = self.register()?;
+                    self.emit(if text == "save" {
+                        Opcode::SaveRange { x, y }
+                    } else {
+                        Opcode::LoadRange { x, y }
+                    })?;
+                } else {
+                    self.emit(if text == "save" {
+                        Opcode::Store { x }
+                    } else {
+                        Opcode::Load { x }
+                    })?;
+                }
+            }
+            "saveflags" => self.register_opcode(|x| Opcode::SaveFlags { x })?,
+            "loadflags" => self.register_opcode(|x| Opcode::LoadFlags { x })?,
+            "bcd" => self.register_opcode(|x| Opcode::Bcd { x })?,
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.integer(0, 0xF)? as u8;
+                self.emit(Opcode::Draw { x, y, n })?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                match text {
+                    "delay" => self.register_opcode(|x| Opcode::SetDelay { x })?,
+                    "buzzer" => self.register_opcode(|x| Opcode::SetSound { x })?,
+                    _ => self.register_opcode(|x| Opcode::Pitch { x })?,
+                }
+            }
+            "i" => self.i_statement()?,
+            "if" => self.if_statement()?,
+            "else" => {
+                let Some(Block::If { jump, line }) = self.blocks.pop() else {
+                    return Err(self.error("else without if"));
+                };
+                let else_jump = self.here;
+                self.emit(Opcode::Jump { nnn: 0 })?;
+                self.patch_jump(jump, self.here)?;
+                self.blocks.push(Block::Else {
+                    jump: else_jump,
+                    line,
+                });
+            }
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) | Some(Block::Else { jump, .. }) => {
+                    self.patch_jump(jump, self.here)?;
+                }
+                _ => return Err(self.error("end without if")),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here,
+                breaks: Vec::new(),
+                line: self.line,
+            }),
+            "while" => {
+                let test = self.condition()?;
+                self.emit(test.skip_opcode())?;
+                let jump = self.here;
+                self.emit(Opcode::Jump { nnn: 0 })?;
+                let Some(Block::Loop { breaks, .. }) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                else {
+                    return Err(self.error("while outside of a loop"));
+                };
+                breaks.push(jump);
+            }
+            "again" => {
+                let Some(Block::Loop { start, breaks, .. }) = self.blocks.pop() else {
+                    return Err(self.error("again without loop"));
+                };
+                let jump = self.here;
+                self.emit(Opcode::Jump { nnn: 0 })?;
+                self.patch_jump(jump, start)?;
+                for jump in breaks {
+                    self.patch_jump(jump, self.here)?;
+                }
+            }
+            _ => {
+                if let Some(reg) = self.lookup_register(text) {
+                    return self.register_statement(reg);
+                }
+                if let Some(body) = self.expand_macro(text)? {
+                    for token in body.into_iter().rev() {
+                        self.tokens.push_front(token);
+                    }
+                    return Ok(());
+                }
+                if let Some(addr) = self.labels.get(text).copied() {
+                    if addr > 0xFFF {
+                        return Err(self.error(&format!("Can't call {text} above 0xFFF")));
+                    }
+                    return self.emit(Opcode::Call { nnn: addr });
+                }
+                if let Some(value) = self.lookup_value(text) {
+                    let byte = to_byte(value as i64)
+                        .ok_or_else(|| self.error(&format!("Byte {text} out of range")))?;
+                    return self.emit_byte(byte);
+                }
+                if text.starts_with(':') || !is_name(text) {
+                    return Err(self.error(&format!("Unknown statement {text}")));
+                }
+                //Any other name calls the label, which may not be defined yet.
+                let addr = self.here;
+                self.emit(Opcode::Call { nnn: 0 })?;
+                self.fixups.push(Fixup {
+                    addr,
+                    kind: FixupKind::Nnn,
+                    name: token.text,
+                    line: self.line,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<(), AsmError> {
+        if !is_name(name) {
+            return Err(self.error(&format!("Invalid label name {name}")));
+        }
+        if self.labels.contains_key(name) {
+            return Err(self.error(&format!("Label {name} defined twice")));
+        }
+        self.labels.insert(name.to_string(), self.here as u16);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.expect_token()?.text;
+        let mut args = Vec::new();
+        loop {
+            let token = self.expect_token()?;
+            if token.text == "{" {
+                break;
+            }
+            args.push(token.text);
+        }
+        let body = self.braced_tokens()?;
+        self.macros.insert(name, Macro { args, body });
+        Ok(())
+    }
+
+    //Tokens up to the closing brace, the opening brace already taken.
+    fn braced_tokens(&mut self) -> Result<Vec<Token>, AsmError> {
+        let mut depth = 1;
+        let mut body = Vec::new();
+        loop {
+            let token = self.expect_token()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(body);
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    //Macro body with its arguments substituted, all on the line of the invocation.
+    fn expand_macro(&mut self, name: &str) -> Result<Option<Vec<Token>>, AsmError> {
+        let Some(arg_count) = self.macros.get(name).map(|mac| mac.args.len()) else {
+            return Ok(None);
+        };
+        let line = self.line;
+        let values = (0..arg_count)
+            .map(|_| self.expect_token().map(|token| token.text))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mac = &self.macros[name];
+        let body = mac
+            .body
+            .iter()
+            .map(|token| {
+                let text = mac
+                    .args
+                    .iter()
+                    .position(|arg| *arg == token.text)
+                    .map_or_else(|| token.text.clone(), |index| values[index].clone());
+                Token { text, line }
+            })
+            .collect();
+        Ok(Some(body))
+    }
+
+    fn i_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.expect_token()?.text;
+        match op.as_str() {
+            ":=" => {}
+            "+=" => return self.register_opcode(|x| Opcode::AddI { x }),
+            _ => return Err(self.error(&format!("Unknown operator i {op}"))),
+        }
+        if self.peek_is("hex") {
+            self.next_token();
+            return self.register_opcode(|x| Opcode::LoadFont { x });
+        }
+        if self.peek_is("bighex") {
+            self.next_token();
+            return self.register_opcode(|x| Opcode::LoadBigFont { x });
+        }
+        if self.peek_is("long") {
+            self.next_token();
+            let token = self.expect_token()?;
+            self.emit(Opcode::LoadILong)?;
+            let addr = self.here;
+            match self.lookup_value(&token.text) {
+                Some(value) => {
+                    let long = to_range(value as i64, 0, 0xFFFF)
+                        .ok_or_else(|| self.error("Address out of range"))?;
+                    self.emit_word(long as u16)?;
+                }
+                None => {
+                    self.emit_word(0x0000)?;
+                    self.fixups.push(Fixup {
+                        addr,
+                        kind: FixupKind::Long,
+                        name: token.text,
+                        line: self.line,
+                    });
+                }
+            }
+            return Ok(());
+        }
+        self.address_opcode(|nnn| Opcode::LoadI { nnn }.encode())
+    }
+
+    fn register_statement(&mut self, x: usize) -> Result<(), AsmError> {
+        let op = self.expect_token()?.text;
+        if op == ":=" {
+            if self.peek_is("random") {
+                self.next_token();
+                let kk = self.byte()?;
+                return self.emit(Opcode::Random { x, kk });
+            }
+            if self.peek_is("key") {
+                self.next_token();
+                return self.emit(Opcode::WaitKey { x });
+            }
+            if self.peek_is("delay") {
+                self.next_token();
+                return self.emit(Opcode::GetDelay { x });
+            }
+        }
+        let rhs = self.operand()?;
+        let opcode = match (op.as_str(), rhs) {
+            (":=", Operand::Imm(kk)) => Opcode::LoadImm { x, kk },
+            ("+=", Operand::Imm(kk)) => Opcode::AddImm { x, kk },
+            ("-=", Operand::Imm(kk)) => Opcode::AddImm {
+                x,
+                kk: kk.wrapping_neg(),
+            },
+            (":=", Operand::Reg(y)) => Opcode::Move { x, y },
+            ("|=", Operand::Reg(y)) => Opcode::Or { x, y },
+            ("&=", Operand::Reg(y)) => Opcode::And { x, y },
+            ("^=", Operand::Reg(y)) => Opcode::Xor { x, y },
+            ("+=", Operand::Reg(y)) => Opcode::AddReg { x, y },
+            ("-=", Operand::Reg(y)) => Opcode::Sub { x, y },
+            (">>=", Operand::Reg(y)) => Opcode::ShiftRight { x, y },
+            ("=-", Operand::Reg(y)) => Opcode::SubReverse { x, y },
+            ("<<=", Operand::Reg(y)) => Opcode::ShiftLeft { x, y },
+            _ => return Err(self.error(&format!("Unknown operator v{x:x} {op}"))),
+        };
+        self.emit(opcode)
+    }
+
+    fn if_statement(&mut self) -> Result<(), AsmError> {
+        let test = self.condition()?;
+        let form = self.expect_token()?.text;
+        match form.as_str() {
+            "then" => self.emit(test.negate().skip_opcode()),
+            "begin" => {
+                self.emit(test.skip_opcode())?;
+                let jump = self.here;
+                self.emit(Opcode::Jump { nnn: 0 })?;
+                self.blocks.push(Block::If {
+                    jump,
+                    line: self.line,
+                });
+                Ok(())
+            }
+            _ => Err(self.error(&format!("Expected then or begin, found {form}"))),
+        }
+    }
+
+    //Parses a condition, emitting any setup it needs through VF.
+    fn condition(&mut self) -> Result<Test, AsmError> {
+        let x = self.register()?;
+        let op = self.expect_token()?.text;
+        match op.as_str() {
+            "key" => return Ok(Test::Key(x)),
+            "-key" => return Ok(Test::NotKey(x)),
+            _ => {}
+        }
+        let rhs = self.operand()?;
+        match op.as_str() {
+            "==" => return Ok(Test::Eq(x, rhs)),
+            "!=" => return Ok(Test::Ne(x, rhs)),
+            _ => {}
+        }
+        //Inequalities subtract into VF and test the carry left behind.
+        let (reversed, holds_when_carry) = match op.as_str() {
+            ">=" => (false, true),
+            "<" => (false, false),
+            "<=" => (true, true),
+            ">" => (true, false),
+            _ => return Err(self.error(&format!("Unknown comparison {op}"))),
+        };
+        let setup = match (rhs, reversed) {
+            //VF = x - y, carry when x >= y.
+            (Operand::Reg(y), false) => [Opcode::Move { x: 0xF, y: x }, Opcode::Sub { x: 0xF, y }],
+            //VF = y - x, carry when y >= x.
+            (Operand::Reg(y), true) => [
+                Opcode::Move { x: 0xF, y: x },
+                Opcode::SubReverse { x: 0xF, y },
+            ],
+            (Operand::Imm(kk), false) => [
+                Opcode::LoadImm { x: 0xF, kk },
+                Opcode::SubReverse { x: 0xF, y: x },
+            ],
+            (Operand::Imm(kk), true) => {
+                [Opcode::LoadImm { x: 0xF, kk }, Opcode::Sub { x: 0xF, y: x }]
+            }
+        };
+        for opcode in setup {
+            self.emit(opcode)?;
+        }
+        Ok(if holds_when_carry {
+            Test::Ne(0xF, Operand::Imm(0))
+        } else {
+            Test::Eq(0xF, Operand::Imm(0))
+        })
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        let is_register = self
+            .tokens
+            .front()
+            .is_some_and(|token| self.lookup_register(&token.text).is_some());
+        if is_register {
+            Ok(Operand::Reg(self.register()?))
+        } else {
+            Ok(Operand::Imm(self.byte()?))
+        }
+    }
+
+    fn register(&mut self) -> Result<usize, AsmError> {
+        let token = self.expect_token()?;
+        self.lookup_register(&token.text)
+            .ok_or_else(|| self.error(&format!("Expected a register, found {}", token.text)))
+    }
+
+    fn lookup_register(&self, text: &str) -> Option<usize> {
+        if let Some(reg) = self.aliases.get(text) {
+            return Some(*reg);
+        }
+        let index = text.strip_prefix(['v', 'V'])?;
+        if index.len() != 1 {
+            return None;
+        }
+        usize::from_str_radix(index, 16).ok()
+    }
+
+    fn register_opcode(&mut self, opcode: fn(usize) -> Opcode) -> Result<(), AsmError> {
+        let x = self.register()?;
+        self.emit(opcode(x))
+    }
+
+    //Emits the opcode for NNN, fixing up labels that aren't defined yet.
+    fn address_opcode(&mut self, opcode: fn(u16) -> Result<u16, EmuErr>) -> Result<(), AsmError> {
+        let token = self.expect_token()?;
+        match self.lookup_value(&token.text) {
+            Some(value) => {
+                let nnn = to_range(value as i64, 0, 0xFFF)
+                    .ok_or_else(|| self.error(&format!("Address {} out of range", token.text)))?;
+                let word = opcode(nnn as u16).map_err(|err| self.error(&err.to_string()))?;
+                self.emit_word(word)
+            }
+            None if is_name(&token.text) => {
+                let addr = self.here;
+                let word = opcode(0).map_err(|err| self.error(&err.to_string()))?;
+                self.emit_word(word)?;
+                self.fixups.push(Fixup {
+                    addr,
+                    kind: FixupKind::Nnn,
+                    name: token.text,
+                    line: self.line,
+                });
+                Ok(())
+            }
+            None => Err(self.error(&format!("Expected an address, found {}", token.text))),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let value = self.value()? as i64;
+        to_byte(value).ok_or_else(|| self.error(&format!("Byte {value} out of range")))
+    }
+
+    fn integer(&mut self, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.value()? as i64;
+        to_range(value, min, max)
+            .ok_or_else(|| self.error(&format!("{value} outside of {min}..={max}")))
+    }
+
+    fn value(&mut self) -> Result<f64, AsmError> {
+        let token = self.expect_token()?;
+        self.lookup_value(&token.text)
+            .ok_or_else(|| self.error(&format!("Undefined value {}", token.text)))
+    }
+
+    //Numbers, constants and labels that are already defined.
+    fn lookup_value(&self, text: &str) -> Option<f64> {
+        if let Some(value) = parse_number(text) {
+            return Some(value as f64);
+        }
+        if let Some(value) = self.consts.get(text) {
+            return Some(*value);
+        }
+        self.labels.get(text).map(|addr| *addr as f64)
+    }
+
+    fn braced_expression(&mut self) -> Result<f64, AsmError> {
+        self.expect("{")?;
+        let tokens = self.braced_tokens()?;
+        let mut tokens: VecDeque<_> = tokens.into_iter().map(|token| token.text).collect();
+        let value = self.expression(&mut tokens)?;
+        if let Some(token) = tokens.front() {
+            return Err(self.error(&format!("Unexpected {token} in expression")));
+        }
+        Ok(value)
+    }
+
+    //Octo expressions have no precedence, binary operators are applied right to left.
+    fn expression(&self, tokens: &mut VecDeque<String>) -> Result<f64, AsmError> {
+        let lhs = self.term(tokens)?;
+        let Some(op) = tokens.front().cloned() else {
+            return Ok(lhs);
+        };
+        if op == ")" {
+            return Ok(lhs);
+        }
+        tokens.pop_front();
+        let rhs = self.expression(tokens)?;
+        let (lhs_int, rhs_int) = (lhs as i64, rhs as i64);
+        Ok(match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => {
+                if rhs == 0.0 {
+                    return Err(self.error("Division by zero"));
+                }
+                lhs / rhs
+            }
+            "%" => {
+                if rhs_int == 0 {
+                    return Err(self.error("Division by zero"));
+                }
+                (lhs_int % rhs_int) as f64
+            }
+            "&" => (lhs_int & rhs_int) as f64,
+            "|" => (lhs_int | rhs_int) as f64,
+            "^" => (lhs_int ^ rhs_int) as f64,
+            "<<" | ">>" => {
+                let shifted = u32::try_from(rhs_int).ok().and_then(|rhs| {
+                    if op == "<<" {
+                        lhs_int.checked_shl(rhs)
+                    } else {
+                        lhs_int.checked_shr(rhs)
+                    }
+                });
+                shifted.ok_or_else(|| self.error(&format!("Shift by {rhs_int} out of range")))?
+                    as f64
+            }
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            _ => return Err(self.error(&format!("Unknown operator {op} in expression"))),
+        })
+    }
+
+    fn term(&self, tokens: &mut VecDeque<String>) -> Result<f64, AsmError> {
+        let token = tokens
+            .pop_front()
+            .ok_or_else(|| self.error("Incomplete expression"))?;
+        match token.as_str() {
+            "(" => {
+                let value = self.expression(tokens)?;
+                if tokens.pop_front().as_deref() != Some(")") {
+                    return Err(self.error("Expected ) in expression"));
+                }
+                Ok(value)
+            }
+            "-" => Ok(-self.term(tokens)?),
+            "~" => Ok(!(self.term(tokens)? as i64) as f64),
+            "!" => Ok((self.term(tokens)? == 0.0) as u8 as f64),
+            "HERE" => Ok(self.here as f64),
+            _ => self
+                .lookup_value(&token)
+                .ok_or_else(|| self.error(&format!("Undefined value {token}"))),
+        }
+    }
+
+    //Points the placeholder jump at addr to target.
+    fn patch_jump(&mut self, addr: usize, target: usize) -> Result<(), AsmError> {
+        if target > 0xFFF {
+            return Err(self.error("Block jumps beyond the 12 bit address range"));
+        }
+        self.memory[addr] |= (target >> 8) as u8;
+        self.memory[addr + 1] = target as u8;
+        Ok(())
+    }
+
+    fn emit(&mut self, opcode: Opcode) -> Result<(), AsmError> {
+        let word = opcode
+            .encode()
+            .map_err(|err| self.error(&err.to_string()))?;
+        self.emit_word(word)
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), AsmError> {
+        let [high_byte, low_byte] = word.to_be_bytes();
+        self.emit_byte(high_byte)?;
+        self.emit_byte(low_byte)
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+        if self.here >= MEMORY_SIZE {
+            return Err(self.error("Program doesn't fit in memory"));
+        }
+        if self.written[self.here] {
+            return Err(self.error(&format!("Overwrites address {:#06x}", self.here)));
+        }
+        self.memory[self.here] = byte;
+        self.written[self.here] = true;
+        let addr = self.here as u16;
+        match self.lines.last_mut() {
+            Some(last)
+                if last.line == self.line
+                    && last.addr as usize + last.len as usize == self.here =>
+            {
+                last.len += 1
+            }
+            _ => self.lines.push(SourceLine {
+                addr,
+                len: 1,
+                line: self.line,
+            }),
+        }
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+}
+
+//Accepts decimal, 0x hex and 0b binary, optionally negative.
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
+
+//Bytes may be written signed, -1 is 0xFF.
+fn to_byte(value: i64) -> Option<u8> {
+    to_range(value, -0x80, 0xFF).map(|value| value as u8)
+}
+
+fn to_range(value: i64, min: i64, max: i64) -> Option<i64> {
+    (min..=max).contains(&value).then_some(value)
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8, Chip8Builder};
+
+    fn words(source: &str) -> Vec<u16> {
+        let assembly = assemble(source).unwrap_or_else(|err| panic!("{err}"));
+        assembly
+            .rom
+            .chunks(2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]))
+            .collect()
+    }
+
+    fn error(source: &str) -> AsmError {
+        match assemble(source) {
+            Ok(_) => panic!("{source} assembled"),
+            Err(err) => err,
+        }
+    }
+
+    //Runs the assembled program until it jumps to itself.
+    fn run(source: &str) -> Chip8 {
+        let rom = assemble(source).unwrap_or_else(|err| panic!("{err}")).rom;
+        let mut chip_8 = Chip8Builder::from_preset("schip-modern")
+            .unwrap()
+            .with_program(&rom)
+            .build()
+            .unwrap();
+        for _ in 0..100 {
+            let pc = chip_8.get_pc();
+            chip_8.execute_next().unwrap();
+            if chip_8.get_pc() == pc {
+                return chip_8;
+            }
+        }
+        panic!("{source} didn't finish");
+    }
+
+    #[test]
+    fn if_then_skips_on_the_negated_test() {
+        assert_eq!(words("if v0 == 5 then v1 := 1"), [0x4005, 0x6101]);
+        assert_eq!(words("if v0 != v2 then v1 := 1"), [0x5020, 0x6101]);
+        assert_eq!(words("if v3 key then v1 := 1"), [0xE3A1, 0x6101]);
+        assert_eq!(words("if v3 -key then v1 := 1"), [0xE39E, 0x6101]);
+    }
+
+    #[test]
+    fn begin_else_end_patches_both_jumps() {
+        let source = "if v0 == 1 begin v1 := 1 else v1 := 2 end";
+        assert_eq!(words(source), [0x3001, 0x1208, 0x6101, 0x120A, 0x6102]);
+        assert_eq!(
+            words("if v0 == 1 begin v1 := 1 end"),
+            [0x3001, 0x1206, 0x6101]
+        );
+    }
+
+    #[test]
+    fn loop_while_again_patches_breaks() {
+        let source = "v0 := 0 loop v0 += 1 while v0 != 10 again";
+        assert_eq!(words(source), [0x6000, 0x7001, 0x400A, 0x120A, 0x1202]);
+        let err = error(":org 0x1000 loop again");
+        assert!(err.message.contains("12 bit"), "{err}");
+    }
+
+    #[test]
+    fn comparisons_go_through_vf() {
+        assert_eq!(
+            words("if v0 > v1 then v2 := 1"),
+            [0x8F00, 0x8F17, 0x4F00, 0x6201]
+        );
+        for op in ["<", "<=", ">", ">="] {
+            for (a, b) in [(3u8, 7u8), (7, 7), (7, 3), (0, 255)] {
+                let expected = match op {
+                    "<" => a < b,
+                    "<=" => a <= b,
+                    ">" => a > b,
+                    _ => a >= b,
+                };
+                for rhs in ["v1", &b.to_string()] {
+                    let source = format!(
+                        "v0 := {a} v1 := {b} v2 := 0 if v0 {op} {rhs} then v2 := 1 : done jump done"
+                    );
+                    let v2 = run(&source).get_v_regs()[2];
+                    assert_eq!(v2 == 1, expected, "{source}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn macros_and_calc() {
+        let source = ":macro set reg val { reg := val } set v3 7 set v4 0x10";
+        assert_eq!(words(source), [0x6307, 0x6410]);
+        //No precedence, operators apply right to left.
+        assert_eq!(words(":calc x { 10 - 2 - 3 } v0 := x"), [0x600B]);
+        assert_eq!(words(":calc x { 1 << 4 } v0 := x"), [0x6010]);
+        assert_eq!(words(":calc x { 0x80 >> 3 } v0 := x"), [0x6010]);
+        assert!(error(":calc x { 1 << 100 }").message.contains("Shift"));
+        assert!(error(":calc x { 1 << -1 }").message.contains("Shift"));
+        assert!(error(":calc x { 1 >> 64 }").message.contains("Shift"));
+    }
+
+    #[test]
+    fn unpack_loads_a_label_into_v0_v1() {
+        assert_eq!(
+            words(":unpack 0xA data : data 0xFF 0x01"),
+            [0x60A2, 0x6104, 0xFF01]
+        );
+        let err = error(":unpack 0 data\n:org 0x1000\n: data 0xFF 0x01");
+        assert_eq!(err.line, 1);
+        assert!(err.message.contains("12 bit"), "{err}");
+    }
+
+    #[test]
+    fn forward_labels_are_fixed_up() {
+        let source =
+            "jump start : sub return : start sub i := data i := long data : data 0xFF 0x01";
+        assert_eq!(
+            words(source),
+            [0x1204, 0x00EE, 0x2202, 0xA20C, 0xF000, 0x020C, 0xFF01],
+        );
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.source_map.symbols["data"], 0x20C);
+        assert_eq!(assembly.rom.len(), 14);
+    }
+
+    #[test]
+    fn errors_report_their_line() {
+        assert_eq!(error("v0 := 1\n\nv1 := 300").line, 3);
+        assert_eq!(error("v0 := 1\nundefined\nv1 := 2").line, 2);
+        assert_eq!(error("v0 := 1\nif v0 == 1 begin\nv1 := 2").line, 2);
+        assert_eq!(error(":macro bad { v0 := 300 }\n\nbad").line, 3);
+        assert_eq!(error("loop\nagain\nagain").line, 3);
+    }
+}
@@ -0,0 +1,48 @@
+use chip8::assembler::assemble;
+use std::{fs, path::Path, process};
+
+//Usage: chip8-asm <source.8o> [-o <rom.ch8>] [--map <source map.json>]
+//The ROM is written next to the source unless -o is given.
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut source_path = None;
+    let mut rom_path = None;
+    let mut map_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => rom_path = Some(args.next().unwrap_or_else(|| fail("No path given for -o"))),
+            "--map" => {
+                map_path = Some(
+                    args.next()
+                        .unwrap_or_else(|| fail("No path given for --map")),
+                )
+            }
+            _ => source_path = Some(arg),
+        }
+    }
+    let source_path = source_path.unwrap_or_else(|| fail("No argument given for source."));
+    let source = fs::read_to_string(&source_path)
+        .unwrap_or_else(|err| fail(&format!("Could not read {source_path}: {err}")));
+    let assembly = assemble(&source).unwrap_or_else(|err| fail(&format!("{source_path}: {err}")));
+    let rom_path = rom_path.unwrap_or_else(|| {
+        Path::new(&source_path)
+            .with_extension("ch8")
+            .to_string_lossy()
+            .into_owned()
+    });
+    write(&rom_path, &assembly.rom);
+    if let Some(map_path) = map_path {
+        write(&map_path, assembly.source_map.to_json().as_bytes());
+    }
+}
+
+fn write(path: &str, contents: &[u8]) {
+    if let Err(err) = fs::write(path, contents) {
+        fail(&format!("Could not write {path}: {err}"));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,191 @@
+use chip8::{
+    disassembler::Disassembler,
+    lockstep::{Lockstep, Step, Stop},
+    movie::Movie,
+    Chip8, Chip8Builder,
+};
+use std::{fs, process};
+
+//Frames run when no --frames is given, 10 seconds at 60Hz.
+const DEFAULT_FRAMES: u32 = 600;
+//Both machines get the same random numbers.
+const DEFAULT_SEED: u64 = 0;
+
+struct Options {
+    rom: String,
+    left: Option<String>,
+    right: Option<String>,
+    frames: Option<u32>,
+    tickrate: Option<u32>,
+    seed: u64,
+    movie: Option<String>,
+}
+
+//Usage: chip8-diff <rom> --left <config> --right <config> [--frames <n>] [--tickrate <n>]
+//    [--seed <n>] [--movie <movie>]
+//A config is a platform preset followed by quirks to turn on or off, such as
+//cosmac-vip,-vf-reset or schip-modern,+memory. Runs both in lockstep with the same keys
+//and reports the first instruction after which their state differs, exiting with 1 if
+//they do. Both failing with the same error is reported but isn't a divergence.
+fn main() {
+    let options = parse_args();
+    let program = fs::read(&options.rom)
+        .unwrap_or_else(|err| fail(&format!("Could not read {}: {err}", options.rom)));
+    let movie = options.movie.as_ref().map(|path| {
+        let movie: Movie = fs::read_to_string(path)
+            .unwrap_or_else(|err| fail(&format!("Could not read {path}: {err}")))
+            .parse()
+            .unwrap_or_else(|err: String| fail(&format!("Invalid movie: {err}")));
+        movie.check_rom(&program).unwrap_or_else(|err| fail(&err));
+        movie
+    });
+    let seed = movie.as_ref().map_or(options.seed, |movie| movie.seed);
+    let tickrate = options
+        .tickrate
+        .or(movie.as_ref().map(|movie| movie.tickrate));
+    let build = |config: &Option<String>, side: &str| {
+        let config = config
+            .as_deref()
+            .unwrap_or_else(|| fail(&format!("No config given for --{side}")));
+        let builder = builder_from_config(config).with_seed(seed);
+        let builder = match tickrate {
+            Some(tickrate) => builder.with_tickrate(tickrate),
+            None => builder,
+        };
+        builder
+            .with_program(&program)
+            .build()
+            .unwrap_or_else(|err| fail(&err.to_string()))
+    };
+    let mut lockstep = Lockstep::new(build(&options.left, "left"), build(&options.right, "right"));
+    let frames = options
+        .frames
+        .or(movie.as_ref().map(|movie| movie.frames))
+        .unwrap_or(DEFAULT_FRAMES);
+
+    for frame in 0..frames {
+        if let Some(movie) = &movie {
+            for event in movie.events_at(frame) {
+                if event.pressed {
+                    lockstep.set_key(event.key);
+                } else {
+                    lockstep.unset_key(event.key);
+                }
+            }
+        }
+        match lockstep.run_frame() {
+            Some(Stop::Diverged(divergence)) => {
+                println!(
+                    "Diverged in frame {} after {} instructions: {}",
+                    divergence.frame, divergence.step, divergence.difference
+                );
+                println!("left:  {}", describe(divergence.left, lockstep.left()));
+                println!("right: {}", describe(divergence.right, lockstep.right()));
+                process::exit(1);
+            }
+            Some(Stop::Failed {
+                frame,
+                step,
+                left,
+                right,
+                error,
+            }) => {
+                println!("Both failed in frame {frame} after {step} instructions: {error}");
+                println!("left:  {}", describe(left, lockstep.left()));
+                println!("right: {}", describe(right, lockstep.right()));
+                return;
+            }
+            None => {}
+        }
+        if lockstep.is_halted() {
+            break;
+        }
+    }
+    println!("No divergence in {} frames", lockstep.frame());
+}
+
+//The instruction a side executed, or why it didn't execute one.
+fn describe(step: Option<Step>, chip_8: &Chip8) -> String {
+    let Some(step) = step else {
+        let state = if chip_8.is_halted() {
+            "exited"
+        } else {
+            "waiting for the next frame"
+        };
+        return format!("{state} at {:#05x}", chip_8.get_pc());
+    };
+    let mnemonic = Disassembler::new(chip_8.get_platform())
+        .instruction(&step.opcode.to_be_bytes())
+        .map_or_else(|| "-".to_string(), |(text, _)| text);
+    format!(
+        "{:#05x} {:04x}  {mnemonic} (cycle {})",
+        step.pc, step.opcode, step.cycles
+    )
+}
+
+//<preset>[,+quirk|,-quirk]...
+fn builder_from_config(config: &str) -> Chip8Builder<'static> {
+    let mut parts = config.split(',');
+    let preset = parts.next().unwrap_or_default();
+    let builder = Chip8Builder::from_preset(preset)
+        .unwrap_or_else(|| fail(&format!("Unknown platform preset {preset}")));
+    let mut quirks = builder.get_quirks();
+    for part in parts {
+        let (enabled, name) = match part.split_at_checked(1) {
+            Some(("+", name)) => (true, name),
+            Some(("-", name)) => (false, name),
+            _ => fail(&format!("Expected +quirk or -quirk, not {part}")),
+        };
+        quirks.set(name, enabled).unwrap_or_else(|err| fail(&err));
+    }
+    builder.with_quirks(quirks)
+}
+
+fn parse_args() -> Options {
+    let mut args = std::env::args().skip(1);
+    let mut options = Options {
+        rom: String::new(),
+        left: None,
+        right: None,
+        frames: None,
+        tickrate: None,
+        seed: DEFAULT_SEED,
+        movie: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("No value given for {name}")))
+        };
+        match arg.as_str() {
+            "--left" => options.left = Some(value("--left")),
+            "--right" => options.right = Some(value("--right")),
+            "--frames" => options.frames = Some(parse_number(&value("--frames"), "--frames")),
+            "--tickrate" => {
+                options.tickrate = Some(parse_number(&value("--tickrate"), "--tickrate"))
+            }
+            "--seed" => {
+                let seed = value("--seed");
+                options.seed = seed
+                    .parse()
+                    .unwrap_or_else(|_| fail(&format!("Invalid seed {seed}")));
+            }
+            "--movie" => options.movie = Some(value("--movie")),
+            _ => options.rom = arg,
+        }
+    }
+    if options.rom.is_empty() {
+        fail("No argument given for ROM.");
+    }
+    options
+}
+
+fn parse_number(text: &str, name: &str) -> u32 {
+    text.parse()
+        .unwrap_or_else(|_| fail(&format!("Invalid number {text} for {name}")))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
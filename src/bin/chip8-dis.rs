@@ -0,0 +1,60 @@
+use chip8::{
+    disassembler::{Disassembler, Syntax},
+    rom_db::RomDatabase,
+    Platform,
+};
+use std::{fs, process};
+
+//Usage: chip8-dis <rom> [--platform <preset>] [--syntax octo|cowgod]
+//The platform defaults to the one listed in the ROM database, then SCHIP.
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut rom_path = None;
+    let mut platform = None;
+    let mut syntax = Syntax::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => {
+                let preset = args
+                    .next()
+                    .unwrap_or_else(|| fail("No preset given for --platform"));
+                platform = Some(platform_from_preset(&preset));
+            }
+            "--syntax" => {
+                let name = args
+                    .next()
+                    .unwrap_or_else(|| fail("No syntax given for --syntax"));
+                syntax = name.parse().unwrap_or_else(|err: String| fail(&err));
+            }
+            _ => rom_path = Some(arg),
+        }
+    }
+    let rom_path = rom_path.unwrap_or_else(|| fail("No argument given for ROM."));
+    let program = fs::read(&rom_path)
+        .unwrap_or_else(|err| fail(&format!("Could not read {rom_path}: {err}")));
+    let platform = platform.unwrap_or_else(|| {
+        RomDatabase::bundled()
+            .lookup(&program)
+            .map_or(Platform::default(), |rom_info| rom_info.platform)
+    });
+    print!(
+        "{}",
+        Disassembler::new(platform)
+            .with_syntax(syntax)
+            .listing(&program)
+    );
+}
+
+fn platform_from_preset(preset: &str) -> Platform {
+    match preset {
+        "cosmac-vip" => Platform::Chip8,
+        "schip-legacy" | "schip-modern" => Platform::SuperChip,
+        "xo-chip" => Platform::XoChip,
+        _ => fail(&format!("Unknown platform preset {preset}")),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,426 @@
+use chip8::{
+    assembler::SourceMap,
+    coverage::{self, Source},
+    movie::Movie,
+    profiler::Profiler,
+    rom_db::RomDatabase,
+    screenshot::{self, ImageFormat},
+    trace::{TraceFilter, TraceFormat, Tracer},
+    Chip8, Chip8Builder, Opcode, Timing,
+};
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::PathBuf,
+    process,
+};
+
+//Frames run when no --frames is given, 10 seconds at 60Hz.
+const DEFAULT_FRAMES: u32 = 600;
+//Platform preset for ROMs that aren't in the database.
+const DEFAULT_PRESET: &str = "schip-modern";
+
+//A key held down from frame until frame + frames.
+struct KeyPress {
+    key: usize,
+    frame: u32,
+    frames: u32,
+}
+
+impl KeyPress {
+    //Parses key:frame[:frames], the key in hex and held for 1 frame by default.
+    fn parse(s: &str) -> Option<Self> {
+        let mut fields = s.split(':');
+        let key = usize::from_str_radix(fields.next()?, 16).ok()?;
+        let frame = fields.next()?.parse().ok()?;
+        let frames = match fields.next() {
+            Some(frames) => frames.parse().ok()?,
+            None => 1,
+        };
+        if key > 0xF || fields.next().is_some() {
+            return None;
+        }
+        Some(Self { key, frame, frames })
+    }
+
+    fn is_held(&self, frame: u32) -> bool {
+        (self.frame..self.end()).contains(&frame)
+    }
+
+    //First frame the key is no longer held.
+    fn end(&self) -> u32 {
+        self.frame.saturating_add(self.frames)
+    }
+}
+
+enum Stop {
+    Frames(u32),
+    Pc(u16),
+    Exit,
+    //1NNN jumping to itself, how most programs finish.
+    IdleLoop(u16),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Frames(frames) => write!(f, "Ran {frames} frames"),
+            Stop::Pc(pc) => write!(f, "Reached {pc:#05x}"),
+            Stop::Exit => write!(f, "Program exited"),
+            Stop::IdleLoop(pc) => write!(f, "Idle loop at {pc:#05x}"),
+        }
+    }
+}
+
+struct Options {
+    rom: String,
+    preset: Option<String>,
+    db_dir: Option<PathBuf>,
+    frames: Option<u32>,
+    tickrate: Option<u32>,
+    timing: Option<Timing>,
+    seed: Option<u64>,
+    movie: Option<PathBuf>,
+    until_pc: Option<u16>,
+    stop_on_idle: bool,
+    keys: Vec<KeyPress>,
+    output: Option<PathBuf>,
+    format: Option<ImageFormat>,
+    trace: Option<PathBuf>,
+    trace_format: TraceFormat,
+    trace_filter: TraceFilter,
+    profile: Option<PathBuf>,
+    heatmap: Option<PathBuf>,
+    heatmap_full: bool,
+    coverage: Option<PathBuf>,
+    source_map: Option<PathBuf>,
+    source: Option<String>,
+}
+
+//Usage: chip8-headless <rom> [--platform <preset>] [--db <dir>] [--frames <n>]
+//    [--tickrate <n>] [--timing tickrate|cosmac-vip] [--seed <n>] [--until-pc <addr>]
+//    [--no-idle-stop] [--key <key:frame[:frames]>]... [--movie <movie>] [-o <image>]
+//    [--format png|pbm|ascii] [--trace <file>] [--trace-format text|binary]
+//    [--trace-pc <start>-<end>] [--trace-class flow,skip,alu,memory,display,io]
+//    [--profile <report>] [--heatmap <png>] [--heatmap-full] [--coverage <lcov>]
+//    [--source-map <json>] [--source <source.8o>]
+//Runs until the frame limit, the PC reaching --until-pc, 00FD or a jump to itself and
+//writes the display, as ASCII art to stdout when no -o is given. A movie replays its
+//recorded keys with the platform, quirks, seed and timing it was recorded with.
+//Traces list every executed instruction matching the --trace-pc and --trace-class filters.
+//Profiles rank routines and addresses by instructions executed and annotate the disassembly.
+//The heatmap shows which memory ran as code, was read as data or was never touched, covering
+//0x000-0xFFF unless --heatmap-full asks for all of XO-CHIP's memory.
+//Coverage is written as lcov keyed by address, and by source line given an assembler map.
+fn main() {
+    let options = parse_args();
+    let program = fs::read(&options.rom)
+        .unwrap_or_else(|err| fail(&format!("Could not read {}: {err}", options.rom)));
+    let rom_db = match &options.db_dir {
+        Some(dir) => RomDatabase::from_dir(dir)
+            .unwrap_or_else(|err| fail(&format!("Could not load ROM database: {err}"))),
+        None => RomDatabase::bundled(),
+    };
+    let rom_info = rom_db.lookup(&program);
+    let movie = options.movie.as_ref().map(|path| {
+        let movie: Movie = fs::read_to_string(path)
+            .unwrap_or_else(|err| fail(&format!("Could not read {}: {err}", path.display())))
+            .parse()
+            .unwrap_or_else(|err: String| fail(&format!("Invalid movie: {err}")));
+        movie.check_rom(&program).unwrap_or_else(|err| fail(&err));
+        movie
+    });
+    let builder = match (&options.preset, &rom_info) {
+        (None, Some(rom_info)) => rom_info.configure(Chip8Builder::new()),
+        (preset, _) => {
+            let preset = preset.as_deref().unwrap_or(DEFAULT_PRESET);
+            Chip8Builder::from_preset(preset)
+                .unwrap_or_else(|| fail(&format!("Unknown platform preset {preset}")))
+        }
+    };
+    let builder = match options.tickrate {
+        Some(tickrate) => builder.with_tickrate(tickrate),
+        None => builder,
+    };
+    let builder = match options.timing {
+        Some(timing) => builder.with_timing(timing),
+        None => builder,
+    };
+    let builder = match &options.trace {
+        Some(path) => {
+            let file = fs::File::create(path)
+                .unwrap_or_else(|err| fail(&format!("Could not create {}: {err}", path.display())));
+            let tracer = Tracer::new(io::BufWriter::new(file), options.trace_format)
+                .with_filter(options.trace_filter.clone());
+            builder.with_tracer(tracer)
+        }
+        None => builder,
+    };
+    let builder = match (&movie, options.seed) {
+        (Some(movie), _) => movie.configure(builder),
+        (None, Some(seed)) => builder.with_seed(seed),
+        (None, None) => builder,
+    };
+    let mut chip_8 = builder
+        .with_program(&program)
+        .build()
+        .unwrap_or_else(|err| fail(&err.to_string()));
+    let frames = options
+        .frames
+        .or(movie.as_ref().map(|movie| movie.frames))
+        .unwrap_or(DEFAULT_FRAMES);
+
+    let mut profiler =
+        (options.profile.is_some() || options.heatmap.is_some() || options.coverage.is_some())
+            .then(|| Profiler::new(&chip_8));
+
+    let stop = run(
+        &mut chip_8,
+        &options,
+        movie.as_ref(),
+        profiler.as_mut(),
+        frames,
+    );
+    finish_trace(&mut chip_8);
+    if let Some(profiler) = &profiler {
+        write_profile(profiler, &chip_8, &program, &options);
+    }
+    write_image(&chip_8, &options);
+    match stop {
+        Ok(stop) => eprintln!("{stop}"),
+        Err(err) => fail(&err.to_string()),
+    }
+}
+
+fn write_profile(profiler: &Profiler, chip_8: &Chip8, program: &[u8], options: &Options) {
+    if let Some(path) = &options.profile {
+        let profile = format!(
+            "{}\n{}",
+            profiler.report(chip_8),
+            profiler.annotated_listing(chip_8, program)
+        );
+        if let Err(err) = fs::write(path, profile) {
+            eprintln!("Could not write {}: {err}", path.display());
+        }
+    }
+    if let Some(path) = &options.heatmap {
+        if let Err(err) = fs::write(path, profiler.heatmap_png(options.heatmap_full)) {
+            eprintln!("Could not write {}: {err}", path.display());
+        }
+    }
+    if let Some(path) = &options.coverage {
+        let source_map = options.source_map.as_ref().map(|map_path| {
+            fs::read_to_string(map_path)
+                .map_err(|err| err.to_string())
+                .and_then(|json| SourceMap::from_json(&json).map_err(|err| err.to_string()))
+                .unwrap_or_else(|err| {
+                    fail(&format!("Invalid source map {}: {err}", map_path.display()))
+                })
+        });
+        //Maps are written next to their source by convention.
+        let source_path = options.source.clone().or_else(|| {
+            let map_path = options.source_map.as_ref()?;
+            Some(map_path.with_extension("8o").to_string_lossy().into_owned())
+        });
+        let source = source_map
+            .as_ref()
+            .zip(source_path.as_deref())
+            .map(|(map, path)| Source { path, map });
+        let report = coverage::lcov(profiler, &options.rom, program.len(), source.as_ref());
+        if let Err(err) = fs::write(path, report) {
+            eprintln!("Could not write {}: {err}", path.display());
+        }
+    }
+}
+
+fn finish_trace(chip_8: &mut Chip8) {
+    if let Some(Err(err)) = chip_8.take_tracer().map(Tracer::finish) {
+        eprintln!("Could not write trace: {err}");
+    }
+}
+
+fn run(
+    chip_8: &mut Chip8,
+    options: &Options,
+    movie: Option<&Movie>,
+    mut profiler: Option<&mut Profiler>,
+    frames: u32,
+) -> Result<Stop, chip8::EmuErr> {
+    for frame in 0..frames {
+        if let Some(movie) = movie {
+            movie.apply(frame, chip_8);
+        }
+        //Only released as a press ends, leaving the movie's keys alone. Released first so
+        //overlapping presses of the same key keep it held.
+        for press in options.keys.iter().filter(|press| press.end() == frame) {
+            chip_8.unset_key(press.key);
+        }
+        for press in options.keys.iter().filter(|press| press.is_held(frame)) {
+            chip_8.set_key(press.key);
+        }
+        //Same steps as Chip8::run_frame, checking the stop conditions after each instruction.
+        chip_8.start_frame();
+        while !chip_8.is_frame_done() {
+            match profiler.as_deref_mut() {
+                Some(profiler) => profiler.execute_next(chip_8)?,
+                None => chip_8.execute_next()?,
+            }
+            let pc = chip_8.get_pc();
+            if chip_8.is_halted() {
+                return Ok(Stop::Exit);
+            }
+            if options.until_pc == Some(pc) {
+                return Ok(Stop::Pc(pc));
+            }
+            if options.stop_on_idle && is_idle_loop(chip_8) {
+                return Ok(Stop::IdleLoop(pc));
+            }
+        }
+    }
+    Ok(Stop::Frames(frames))
+}
+
+fn is_idle_loop(chip_8: &Chip8) -> bool {
+    let pc = chip_8.get_pc();
+    let Some(bytes) = chip_8.get_memory().get(pc as usize..pc as usize + 2) else {
+        return false;
+    };
+    let word = u16::from_be_bytes([bytes[0], bytes[1]]);
+    matches!(
+        Opcode::decode(word, chip_8.get_platform()),
+        Ok(Opcode::Jump { nnn }) if nnn == pc
+    )
+}
+
+fn write_image(chip_8: &Chip8, options: &Options) {
+    let format = options
+        .format
+        .or_else(|| options.output.as_deref().and_then(ImageFormat::from_path))
+        .unwrap_or(ImageFormat::Ascii);
+    let image = screenshot::encode(chip_8, format);
+    let written = match &options.output {
+        Some(path) => fs::write(path, image),
+        None => io::stdout().write_all(&image),
+    };
+    if let Err(err) = written {
+        fail(&format!("Could not write image: {err}"));
+    }
+}
+
+fn parse_args() -> Options {
+    let mut args = std::env::args().skip(1);
+    let mut options = Options {
+        rom: String::new(),
+        preset: None,
+        db_dir: None,
+        frames: None,
+        tickrate: None,
+        timing: None,
+        seed: None,
+        movie: None,
+        until_pc: None,
+        stop_on_idle: true,
+        keys: Vec::new(),
+        output: None,
+        format: None,
+        trace: None,
+        trace_format: TraceFormat::default(),
+        trace_filter: TraceFilter::default(),
+        profile: None,
+        heatmap: None,
+        heatmap_full: false,
+        coverage: None,
+        source_map: None,
+        source: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("No value given for {name}")))
+        };
+        match arg.as_str() {
+            "--platform" => options.preset = Some(value("--platform")),
+            "--db" => options.db_dir = Some(value("--db").into()),
+            "--frames" => options.frames = Some(parse_number(&value("--frames"), "--frames")),
+            "--movie" => options.movie = Some(value("--movie").into()),
+            "--tickrate" => {
+                options.tickrate = Some(parse_number(&value("--tickrate"), "--tickrate"))
+            }
+            "--timing" => {
+                let timing = value("--timing");
+                options.timing = Some(timing.parse().unwrap_or_else(|err: String| fail(&err)));
+            }
+            "--seed" => {
+                let seed = value("--seed");
+                options.seed = Some(
+                    seed.parse()
+                        .unwrap_or_else(|_| fail(&format!("Invalid seed {seed}"))),
+                );
+            }
+            "--until-pc" => {
+                let addr = value("--until-pc");
+                let addr =
+                    parse_addr(&addr).unwrap_or_else(|| fail(&format!("Invalid address {addr}")));
+                options.until_pc = Some(addr);
+            }
+            "--no-idle-stop" => options.stop_on_idle = false,
+            "--key" => {
+                let press = value("--key");
+                options.keys.push(
+                    KeyPress::parse(&press)
+                        .unwrap_or_else(|| fail(&format!("Invalid key press {press}"))),
+                );
+            }
+            "--trace" => options.trace = Some(value("--trace").into()),
+            "--trace-format" => {
+                let format = value("--trace-format");
+                options.trace_format = format.parse().unwrap_or_else(|err: String| fail(&err));
+            }
+            "--trace-pc" => {
+                let range = value("--trace-pc");
+                let pcs = range
+                    .split_once('-')
+                    .and_then(|(start, end)| Some(parse_addr(start)?..=parse_addr(end)?))
+                    .unwrap_or_else(|| fail(&format!("Invalid address range {range}")));
+                options.trace_filter.pcs = Some(pcs);
+            }
+            "--trace-class" => {
+                for class in value("--trace-class").split(',') {
+                    let class = class.parse().unwrap_or_else(|err: String| fail(&err));
+                    options.trace_filter.classes.push(class);
+                }
+            }
+            "--profile" => options.profile = Some(value("--profile").into()),
+            "--heatmap" => options.heatmap = Some(value("--heatmap").into()),
+            "--heatmap-full" => options.heatmap_full = true,
+            "--coverage" => options.coverage = Some(value("--coverage").into()),
+            "--source-map" => options.source_map = Some(value("--source-map").into()),
+            "--source" => options.source = Some(value("--source")),
+            "-o" => options.output = Some(value("-o").into()),
+            "--format" => {
+                let format = value("--format");
+                options.format = Some(format.parse().unwrap_or_else(|err: String| fail(&err)));
+            }
+            _ => options.rom = arg,
+        }
+    }
+    if options.rom.is_empty() {
+        fail("No argument given for ROM.");
+    }
+    options
+}
+
+//Hex, with or without 0x.
+fn parse_addr(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.trim_start_matches("0x"), 16).ok()
+}
+
+fn parse_number(text: &str, name: &str) -> u32 {
+    text.parse()
+        .unwrap_or_else(|_| fail(&format!("Invalid number {text} for {name}")))
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
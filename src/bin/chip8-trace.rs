@@ -0,0 +1,50 @@
+use chip8::trace::TraceReader;
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    process,
+};
+
+//Usage: chip8-trace <binary trace> [-o <text trace>]
+//Converts a binary trace written by chip8-headless --trace-format binary to text.
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut trace_path = None;
+    let mut output_path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => {
+                output_path = Some(args.next().unwrap_or_else(|| fail("No path given for -o")));
+            }
+            _ => trace_path = Some(arg),
+        }
+    }
+    let trace_path = trace_path.unwrap_or_else(|| fail("No argument given for trace."));
+    let file = File::open(&trace_path)
+        .unwrap_or_else(|err| fail(&format!("Could not read {trace_path}: {err}")));
+    let reader = TraceReader::new(BufReader::new(file))
+        .unwrap_or_else(|err| fail(&format!("Invalid trace {trace_path}: {err}")));
+    let output: Box<dyn Write> = match &output_path {
+        Some(path) => Box::new(
+            File::create(path)
+                .unwrap_or_else(|err| fail(&format!("Could not create {path}: {err}"))),
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    if let Err(err) = convert(reader, BufWriter::new(output)) {
+        fail(&err.to_string());
+    }
+}
+
+fn convert(reader: TraceReader<impl io::Read>, mut output: impl Write) -> io::Result<()> {
+    let platform = reader.platform();
+    for entry in reader {
+        writeln!(output, "{}", entry?.to_text(platform))?;
+    }
+    output.flush()
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
@@ -0,0 +1,69 @@
+pub const AUDIO_PATTERN_LEN: usize = 0x10;
+const PATTERN_BITS: f64 = (AUDIO_PATTERN_LEN * 8) as f64;
+//Pitch register value that plays the pattern back at 4000 bits per second.
+const DEFAULT_PITCH: u8 = 64;
+
+//XO-CHIP audio, a 128 bit 1-bit sample loop loaded by F002 and played back at the FX3A pitch.
+#[derive(Debug, Clone)]
+pub struct AudioPattern {
+    pattern: [u8; AUDIO_PATTERN_LEN],
+    pitch: u8,
+    //Playback position within the pattern, in bits.
+    position: f64,
+}
+
+impl AudioPattern {
+    pub fn new() -> Self {
+        Self {
+            pattern: [0; AUDIO_PATTERN_LEN],
+            pitch: DEFAULT_PITCH,
+            position: 0.0,
+        }
+    }
+
+    pub fn pattern(&self) -> &[u8; AUDIO_PATTERN_LEN] {
+        &self.pattern
+    }
+
+    pub fn set_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_LEN]) {
+        self.pattern = *pattern;
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+
+    //Pattern playback rate in bits per second, 4000*2^((pitch-64)/48).
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - DEFAULT_PITCH as f64) / 48.0)
+    }
+
+    //Renders the looping pattern as samples of +-1.0 at the given sample rate. A pattern of
+    //all 0s or all 1s is flat, so it's played as silence rather than a constant offset.
+    pub fn fill_audio(&mut self, out: &mut [f32], sample_rate: u32) {
+        let step = self.playback_rate() / sample_rate as f64;
+        if self.pattern.iter().all(|byte| *byte == 0x00)
+            || self.pattern.iter().all(|byte| *byte == 0xFF)
+        {
+            out.fill(0.0);
+            self.position = (self.position + step * out.len() as f64) % PATTERN_BITS;
+            return;
+        }
+        for sample in out.iter_mut() {
+            let bit = self.position as usize;
+            let bit_set = (self.pattern[bit / 8] >> (7 - bit % 8)) & 0x1 == 0x1;
+            *sample = if bit_set { 1.0 } else { -1.0 };
+            self.position = (self.position + step) % PATTERN_BITS;
+        }
+    }
+}
+
+impl Default for AudioPattern {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,11 +1,18 @@
-use super::{insert_slice::InsertSlice, *};
+use super::{
+    insert_slice::InsertSlice,
+    save_state::{bad_state, StateReader, StateWriter},
+    *,
+};
+use crate::trace::{TraceEntry, Tracer};
 use bit_vec::*;
-use rand::{thread_rng, Rng};
 
 pub const DISPLAY_WIDTH: usize = 0x80;
 pub const DISPLAY_HEIGHT: usize = 0x40;
-const PG_START: usize = 0x200;
-const MEM_SIZE: usize = 0x1000;
+pub const PG_START: usize = 0x200;
+//Instructions executed per 60Hz frame unless the builder is given a tickrate.
+pub const DEFAULT_TICKRATE: u32 = 1000;
+//Number of bytes per sprite row when drawing 16x16 sprites.
+const BIG_SPRITE_LEN: usize = 0x20;
 const FONT_DATA: [u8; 0xF0] = [
     //CHIP8 fonts
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -43,24 +50,63 @@ const FONT_DATA: [u8; 0xF0] = [
     0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
 ];
 
-struct Quirks {
-    vf_reset_quirk: bool,
-    jumping_quirk: bool,
-}
-
 pub struct Chip8Builder<'a> {
     program: Option<&'a [u8]>,
+    platform: Platform,
     quirks: Quirks,
+    write_protection: bool,
+    rng: Option<Box<dyn Rng>>,
+    tickrate: u32,
+    timing: Timing,
+    tracer: Option<Tracer>,
 }
 
 impl<'a> Chip8Builder<'a> {
     pub fn new() -> Self {
         Self {
             program: None,
-            quirks: Quirks {
-                vf_reset_quirk: false,
-                jumping_quirk: false,
-            },
+            platform: Platform::default(),
+            quirks: Quirks::default(),
+            write_protection: false,
+            rng: None,
+            tickrate: DEFAULT_TICKRATE,
+            timing: Timing::default(),
+            tracer: None,
+        }
+    }
+
+    pub fn cosmac_vip() -> Self {
+        Self::new()
+            .with_platform(Platform::Chip8)
+            .with_quirks(Quirks::cosmac_vip())
+    }
+
+    pub fn schip_legacy() -> Self {
+        Self::new()
+            .with_platform(Platform::SuperChip)
+            .with_quirks(Quirks::schip_legacy())
+    }
+
+    pub fn schip_modern() -> Self {
+        Self::new()
+            .with_platform(Platform::SuperChip)
+            .with_quirks(Quirks::schip_modern())
+    }
+
+    pub fn xo_chip() -> Self {
+        Self::new()
+            .with_platform(Platform::XoChip)
+            .with_quirks(Quirks::xo_chip())
+    }
+
+    //Preset by name: cosmac-vip, schip-legacy, schip-modern or xo-chip.
+    pub fn from_preset(name: &str) -> Option<Self> {
+        match name {
+            "cosmac-vip" => Some(Self::cosmac_vip()),
+            "schip-legacy" => Some(Self::schip_legacy()),
+            "schip-modern" => Some(Self::schip_modern()),
+            "xo-chip" => Some(Self::xo_chip()),
+            _ => None,
         }
     }
 
@@ -69,92 +115,314 @@ impl<'a> Chip8Builder<'a> {
         self
     }
 
+    pub fn with_platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    //Quirks set so far, for adjusting a preset.
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
     pub fn with_vf_reset_quirk(mut self) -> Self {
         self.quirks.vf_reset_quirk = true;
         self
     }
 
+    pub fn with_memory_quirk(mut self) -> Self {
+        self.quirks.memory_quirk = true;
+        self
+    }
+
+    pub fn with_display_wait_quirk(mut self) -> Self {
+        self.quirks.display_wait_quirk = true;
+        self
+    }
+
+    pub fn with_clipping_quirk(mut self) -> Self {
+        self.quirks.clipping_quirk = true;
+        self
+    }
+
+    pub fn with_shifting_quirk(mut self) -> Self {
+        self.quirks.shifting_quirk = true;
+        self
+    }
+
     pub fn with_jumping_quirk(mut self) -> Self {
         self.quirks.jumping_quirk = true;
         self
     }
 
+    pub fn with_half_scroll_quirk(mut self) -> Self {
+        self.quirks.half_scroll_quirk = true;
+        self
+    }
+
+    //Writes below the program area, where the fonts live, raise ProtectedWrite.
+    pub fn with_write_protection(mut self) -> Self {
+        self.write_protection = true;
+        self
+    }
+
+    //Random source for CXNN, an entropy seeded XorShift by default.
+    pub fn with_rng(mut self, rng: impl Rng + 'static) -> Self {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    //Deterministic mode, identical seeds and inputs give identical runs.
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.with_rng(XorShift::new(seed))
+    }
+
+    //Instructions executed by each run_frame.
+    pub fn with_tickrate(mut self, tickrate: u32) -> Self {
+        self.tickrate = tickrate;
+        self
+    }
+
+    //Timing::CosmacVip ignores the tickrate, frames run as many instructions as fit in VIP cycles.
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    //Traces every executed instruction the tracer's filter matches.
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
     pub fn build(self) -> Result<Chip8, EmuErr> {
         let program = self
             .program
             .expect("Program must be loaded to build emulator");
-        Chip8::new(self.quirks, program)
+        let mut chip_8 = Chip8::new(self.platform, self.quirks, program)?;
+        chip_8.write_protection = self.write_protection;
+        chip_8.tickrate = self.tickrate;
+        chip_8.timing = self.timing;
+        if let Some(mut tracer) = self.tracer {
+            tracer.start(chip_8.platform);
+            chip_8.tracer = Some(tracer);
+        }
+        if let Some(rng) = self.rng {
+            chip_8.rng = rng;
+        }
+        Ok(chip_8)
+    }
+}
+
+impl Default for Chip8Builder<'_> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 pub struct Chip8 {
+    platform: Platform,
     quirks: Quirks,
-    memory: [u8; 0x1000],
+    memory: Box<[u8]>,
     stack: Stack,
     v_reg: [u8; 0x10],
     i_reg: u16,
     delay_reg: u8,
-    _sound_reg: u8,
+    sound_reg: u8,
     pc: u16,
     pressed_keys: [bool; 0x10],
-    display_buffer: Box<[BitVec]>,
+    //XO-CHIP has two bit planes, other platforms only ever draw to the first.
+    display_planes: [Box<[BitVec]>; 2],
+    selected_planes: u8,
     high_res: bool,
+    rpl_flags: [u8; 0x10],
+    halted: bool,
+    //Set by DXYN with the display wait quirk, execution resumes on the next timer tick.
+    waiting_for_vblank: bool,
+    audio_pattern: AudioPattern,
+    write_protection: bool,
+    //Memory read or written by the last executed instruction.
+    mem_accesses: Vec<MemAccess>,
+    rng: Box<dyn Rng>,
+    tickrate: u32,
+    timing: Timing,
+    //What's left of the current frame's budget, negative once an instruction overruns it.
+    frame_budget: i64,
+    //Total cost of the instructions executed, in timing units. Not part of save states.
+    cycles: u64,
+    tracer: Option<Tracer>,
 }
 
 impl Chip8 {
     //Uses slice of bytes as program data.
-    fn new(quirks: Quirks, program: &[u8]) -> Result<Self, EmuErr> {
+    fn new(platform: Platform, quirks: Quirks, program: &[u8]) -> Result<Self, EmuErr> {
         let pg_len = program.len();
-        let max_len = MEM_SIZE - PG_START;
+        let max_len = platform.memory_size() - PG_START;
         if pg_len > max_len {
             return Err(EmuErr::ProgramLength { pg_len, max_len });
         }
 
         //Program memory.
-        let mut memory = [0u8; MEM_SIZE];
+        let mut memory = vec![0u8; platform.memory_size()].into_boxed_slice();
 
         memory.insert_slice(&FONT_DATA);
 
         //Slice of memory that will hold the program, typically starting at 0x200.
         let mem_pg_slice = &mut memory[PG_START..];
 
-        mem_pg_slice.insert_slice(&program);
+        mem_pg_slice.insert_slice(program);
 
+        let display_plane = vec![BitVec::from_elem(DISPLAY_HEIGHT, false); DISPLAY_WIDTH];
         Ok(Self {
+            platform,
             quirks,
             memory,
             stack: Stack::new(),
             v_reg: [0; 0x10],
             i_reg: 0,
             delay_reg: 0x0,
-            _sound_reg: 0xFF,
+            sound_reg: 0x0,
             pc: PG_START as u16,
             pressed_keys: [false; 0x10],
-            display_buffer: vec![BitVec::from_elem(DISPLAY_HEIGHT, false); DISPLAY_WIDTH]
-                .into_boxed_slice(),
+            display_planes: [
+                display_plane.clone().into_boxed_slice(),
+                display_plane.into_boxed_slice(),
+            ],
+            selected_planes: 0x1,
             high_res: false,
+            rpl_flags: [0; 0x10],
+            halted: false,
+            waiting_for_vblank: false,
+            audio_pattern: AudioPattern::new(),
+            write_protection: false,
+            mem_accesses: Vec::new(),
+            rng: Box::new(XorShift::from_entropy()),
+            tickrate: DEFAULT_TICKRATE,
+            timing: Timing::default(),
+            frame_budget: 0,
+            cycles: 0,
+            tracer: None,
         })
     }
 
     pub fn execute_next(&mut self) -> Result<(), EmuErr> {
-        if self.pc as usize >= self.memory.len() - 1 {
-            return Err(EmuErr::PcOutOfBounds { pc: self.pc });
+        self.mem_accesses.clear();
+        //Nothing runs after the program exits through 00FD or while DXYN waits for the next frame.
+        if self.halted || self.waiting_for_vblank {
+            return Ok(());
         }
 
-        //Merges 2 byte opcode into instruction.
-        let high_byte = (self.memory[self.pc as usize] as u16) << 8;
-        let low_byte = self.memory[self.pc as usize + 1] as u16;
-        let raw_instruction = high_byte | low_byte;
-        let instruction = Instruction::new(raw_instruction);
+        let word = self.fetch_word(self.pc)?;
+        let pc = self.pc;
 
         //PC incremented before execution as jump instructions modify PC.
-        self.pc += 2;
-        self.decode_and_execute(instruction)?;
+        self.pc = self.pc.wrapping_add(2);
+        let v_reg = self.v_reg;
+        //Failing instructions are traced too, they're the ones most worth seeing.
+        let Ok(opcode) = Opcode::decode(word, self.platform) else {
+            let err = EmuErr::BadInstruction {
+                pc: self.pc,
+                instruction: Instruction::new(word),
+            };
+            self.trace(pc, word, None, &v_reg);
+            return Err(err);
+        };
+        let vx = v_reg[Instruction::new(word).x()];
+        let next_pc = self.pc;
+        let result = self.decode_and_execute(opcode);
+        self.trace(pc, word, Some(opcode), &v_reg);
+        result?;
+        let skipped = self.pc != next_pc;
+        let cost = self.timing.cost(opcode, vx, skipped);
+        self.cycles += cost as u64;
+        //On the VIP DXYN waits for the interrupt before drawing, so it's paid for next frame.
+        if self.waiting_for_vblank && self.timing == Timing::CosmacVip {
+            self.frame_budget = -(cost as i64);
+        } else {
+            self.frame_budget -= cost as i64;
+        }
         Ok(())
     }
 
+    //Records the instruction at pc if the tracer wants it, v_reg holding the registers before it ran.
+    fn trace(&mut self, pc: u16, word: u16, opcode: Option<Opcode>, v_reg: &[u8; 0x10]) {
+        let operand = match opcode {
+            Some(Opcode::LoadILong) => self.fetch_word(pc.wrapping_add(2)).ok(),
+            _ => None,
+        };
+        let Some(tracer) = self
+            .tracer
+            .as_mut()
+            .filter(|tracer| tracer.wants(pc, opcode))
+        else {
+            return;
+        };
+        let changed = (0..0x10)
+            .filter(|reg| self.v_reg[*reg] != v_reg[*reg])
+            .map(|reg| (reg, self.v_reg[reg]))
+            .collect();
+        let entry = TraceEntry {
+            cycle: self.cycles,
+            pc,
+            opcode: word,
+            changed,
+            i_reg: self.i_reg,
+            stack_depth: self.stack.as_slice().len() as u8,
+            operand,
+        };
+        tracer.record(self.platform, &entry);
+    }
+
+    //One 60Hz frame, the timers count down then instructions run until the frame's budget
+    //is spent. Fewer run if the program exits or waits for the next frame to draw.
+    pub fn run_frame(&mut self) -> Result<(), EmuErr> {
+        self.start_frame();
+        while !self.is_frame_done() {
+            self.execute_next()?;
+        }
+        Ok(())
+    }
+
+    //The 60Hz interrupt, counts down the timers and refills the frame's budget.
+    //Frontends that step instructions themselves call this, then execute_next until is_frame_done.
+    pub fn start_frame(&mut self) {
+        self.dec_timers();
+        //Overruns are paid back but time left over isn't carried forward.
+        let budget = self.timing.frame_budget(self.tickrate) as i64;
+        self.frame_budget = self.frame_budget.min(0) + budget;
+    }
+
+    pub fn is_frame_done(&self) -> bool {
+        self.frame_budget <= 0 || self.halted || self.waiting_for_vblank
+    }
+
+    //Merges the 2 bytes at addr into a single word.
+    fn fetch_word(&self, addr: u16) -> Result<u16, EmuErr> {
+        if addr as usize >= self.memory.len() - 1 {
+            return Err(EmuErr::PcOutOfBounds { pc: addr });
+        }
+        let high_byte = (self.memory[addr as usize] as u16) << 8;
+        let low_byte = self.memory[addr as usize + 1] as u16;
+        Ok(high_byte | low_byte)
+    }
+
+    //Skips the next instruction, which on XO-CHIP may be the 4 byte F000 NNNN.
+    fn skip_next(&mut self) {
+        let is_long_load = matches!(self.fetch_word(self.pc), Ok(0xF000));
+        if self.platform.has_xo_chip_instructions() && is_long_load {
+            self.pc = self.pc.wrapping_add(4);
+        } else {
+            self.pc = self.pc.wrapping_add(2);
+        }
+    }
+
     fn check_ireg_offset(&self, offset: u16) -> Result<(), EmuErr> {
-        if (self.i_reg + offset) as usize > MEM_SIZE {
+        if self.i_reg as usize + offset as usize > self.memory.len() {
             return Err(EmuErr::IregOverflow {
                 ireg: self.i_reg,
                 offset,
@@ -163,252 +431,389 @@ impl Chip8 {
         Ok(())
     }
 
-    fn decode_and_execute(&mut self, instruction: Instruction) -> Result<(), EmuErr> {
-        let x_reg_ref = &mut self.v_reg[instruction.x()];
-        let kk = instruction.kk();
-        match instruction.high_nibble() {
-            0x0 => match kk {
-                0xE0 => self.clear_display(),
-                0xEE => self.pc = self.stack.pop()?,
-                0xFE => self.high_res = false,
-                0xFF => self.high_res = true,
-                _ => {
-                    return Err(EmuErr::BadInstruction {
-                        pc: self.pc,
-                        instruction,
-                    });
-                }
-            },
-            0x1 => self.pc = instruction.nnn(),
-            0x2 => {
+    fn record_read(&mut self, len: usize) {
+        self.mem_accesses.push(MemAccess {
+            addr: self.i_reg,
+            len: len as u16,
+            kind: AccessKind::Read,
+        });
+    }
+
+    //Called before writing len bytes from I, fails if the write would touch protected memory.
+    fn record_write(&mut self, len: usize) -> Result<(), EmuErr> {
+        if self.write_protection && (self.i_reg as usize) < PG_START {
+            return Err(EmuErr::ProtectedWrite {
+                pc: self.pc.wrapping_sub(2),
+                addr: self.i_reg,
+            });
+        }
+        self.mem_accesses.push(MemAccess {
+            addr: self.i_reg,
+            len: len as u16,
+            kind: AccessKind::Write,
+        });
+        Ok(())
+    }
+
+    //I is left pointing just past the registers stored or loaded by FX55/FX65.
+    fn inc_ireg_past_registers(&mut self, x: usize) -> Result<(), EmuErr> {
+        let offset = x as u16 + 1;
+        self.check_ireg_offset(offset)?;
+        self.i_reg += offset;
+        Ok(())
+    }
+
+    fn decode_and_execute(&mut self, opcode: Opcode) -> Result<(), EmuErr> {
+        use Opcode::*;
+        match opcode {
+            ScrollDown { n } => self.scroll_down(n as usize),
+            ScrollUp { n } => self.scroll_up(n as usize),
+            Clear => self.clear_display(),
+            Return => self.pc = self.stack.pop()?,
+            ScrollRight => self.scroll_right(4),
+            ScrollLeft => self.scroll_left(4),
+            Exit => self.halted = true,
+            LowRes => self.high_res = false,
+            HighRes => self.high_res = true,
+            Jump { nnn } => self.pc = nnn,
+            Call { nnn } => {
                 self.stack.push(self.pc)?;
-                self.pc = instruction.nnn();
+                self.pc = nnn;
             }
-            0x3 => {
-                if *x_reg_ref == kk {
-                    self.pc += 2;
+            SkipEqImm { x, kk } => {
+                if self.v_reg[x] == kk {
+                    self.skip_next();
                 }
             }
-            0x4 => {
-                if *x_reg_ref != kk {
-                    self.pc += 2;
+            SkipNeImm { x, kk } => {
+                if self.v_reg[x] != kk {
+                    self.skip_next();
                 }
             }
-            0x5 => {
-                if *x_reg_ref == self.v_reg[instruction.y()] {
-                    self.pc += 2;
+            SkipEqReg { x, y } => {
+                if self.v_reg[x] == self.v_reg[y] {
+                    self.skip_next();
                 }
             }
-            0x6 => *x_reg_ref = instruction.kk(),
-            0x7 => *x_reg_ref = x_reg_ref.wrapping_add(instruction.kk()),
-            0x8 => return self.instruction_0x8(instruction),
-            0x9 => {
-                if *x_reg_ref != self.v_reg[instruction.y()] {
-                    self.pc += 2;
+            SaveRange { x, y } => {
+                self.check_ireg_offset(x.abs_diff(y) as u16 + 1)?;
+                self.record_write(x.abs_diff(y) + 1)?;
+                let i_reg = self.i_reg as usize;
+                for (offset, reg) in register_range(x, y).enumerate() {
+                    self.memory[i_reg + offset] = self.v_reg[reg];
                 }
             }
-            0xA => self.i_reg = instruction.nnn(),
-            0xB => self.pc = instruction.nnn() + *x_reg_ref as u16,
-            0xC => *x_reg_ref = thread_rng().gen_range(0u8..=255u8) % instruction.kk(),
-            0xD => self.draw(instruction)?,
-            0xE => {
-                let key_pressed = self.pressed_keys[*x_reg_ref as usize];
-                match kk {
-                    0x9E => {
-                        if key_pressed {
-                            self.pc += 2;
-                        }
-                    }
-                    0xA1 => {
-                        if !key_pressed {
-                            self.pc += 2;
-                        }
-                    }
-                    _ => {
-                        return Err(EmuErr::BadInstruction {
-                            pc: self.pc,
-                            instruction,
-                        });
-                    }
+            LoadRange { x, y } => {
+                self.check_ireg_offset(x.abs_diff(y) as u16 + 1)?;
+                self.record_read(x.abs_diff(y) + 1);
+                let i_reg = self.i_reg as usize;
+                for (offset, reg) in register_range(x, y).enumerate() {
+                    self.v_reg[reg] = self.memory[i_reg + offset];
                 }
             }
-            0xF => return self.instruction_0xf(instruction),
-            _ => {}
-        }
-        Ok(())
-    }
-
-    #[inline]
-    fn instruction_0x8(&mut self, instruction: Instruction) -> Result<(), EmuErr> {
-        let y_reg_val = self.v_reg[instruction.y()];
-        let x_reg_ref = &mut self.v_reg[instruction.x()];
-        match instruction.low_nibble() {
-            0x0 => {
-                *x_reg_ref = y_reg_val;
-            }
-            0x1 => {
-                *x_reg_ref |= y_reg_val;
-                if self.quirks.vf_reset_quirk {
-                    self.v_reg[0xF] = 0;
-                }
+            LoadImm { x, kk } => self.v_reg[x] = kk,
+            AddImm { x, kk } => self.v_reg[x] = self.v_reg[x].wrapping_add(kk),
+            Move { x, y } => self.v_reg[x] = self.v_reg[y],
+            Or { x, y } => {
+                self.v_reg[x] |= self.v_reg[y];
+                self.vf_reset();
             }
-            0x2 => {
-                *x_reg_ref &= y_reg_val;
-                if self.quirks.vf_reset_quirk {
-                    self.v_reg[0xF] = 0;
-                }
+            And { x, y } => {
+                self.v_reg[x] &= self.v_reg[y];
+                self.vf_reset();
             }
-            0x3 => {
-                *x_reg_ref ^= y_reg_val;
-                if self.quirks.vf_reset_quirk {
-                    self.v_reg[0xF] = 0;
-                }
+            Xor { x, y } => {
+                self.v_reg[x] ^= self.v_reg[y];
+                self.vf_reset();
             }
-            0x4 => {
-                let did_wrap = x_reg_ref.checked_add(y_reg_val).is_none();
-                *x_reg_ref = x_reg_ref.wrapping_add(y_reg_val);
+            AddReg { x, y } => {
+                let (sum, did_wrap) = self.v_reg[x].overflowing_add(self.v_reg[y]);
+                self.v_reg[x] = sum;
                 self.v_reg[0xF] = did_wrap as u8;
             }
-            0x5 => {
-                let didnt_wrap = x_reg_ref.checked_sub(y_reg_val).is_some();
-                *x_reg_ref = x_reg_ref.wrapping_sub(y_reg_val);
-                self.v_reg[0xF] = didnt_wrap as u8;
+            Sub { x, y } => {
+                let (diff, did_wrap) = self.v_reg[x].overflowing_sub(self.v_reg[y]);
+                self.v_reg[x] = diff;
+                self.v_reg[0xF] = !did_wrap as u8;
             }
-            0x6 => {
-                let lsb = *x_reg_ref & 0x1;
-                *x_reg_ref >>= 1;
+            ShiftRight { x, y } => {
+                if !self.quirks.shifting_quirk {
+                    self.v_reg[x] = self.v_reg[y];
+                }
+                let lsb = self.v_reg[x] & 0x1;
+                self.v_reg[x] >>= 1;
                 self.v_reg[0xF] = lsb;
             }
-            0x7 => {
-                let didnt_wrap = y_reg_val.checked_sub(*x_reg_ref).is_some();
-                *x_reg_ref = y_reg_val.wrapping_sub(*x_reg_ref);
-                self.v_reg[0xF] = didnt_wrap as u8;
+            SubReverse { x, y } => {
+                let (diff, did_wrap) = self.v_reg[y].overflowing_sub(self.v_reg[x]);
+                self.v_reg[x] = diff;
+                self.v_reg[0xF] = !did_wrap as u8;
             }
-            0xE => {
-                let hsb = *x_reg_ref >> 0x7;
-                *x_reg_ref <<= 1;
+            ShiftLeft { x, y } => {
+                if !self.quirks.shifting_quirk {
+                    self.v_reg[x] = self.v_reg[y];
+                }
+                let hsb = self.v_reg[x] >> 0x7;
+                self.v_reg[x] <<= 1;
                 self.v_reg[0xF] = hsb;
             }
-            _ => {
-                return Err(EmuErr::BadInstruction {
-                    pc: self.pc,
-                    instruction,
-                })
+            SkipNeReg { x, y } => {
+                if self.v_reg[x] != self.v_reg[y] {
+                    self.skip_next();
+                }
             }
-        }
-        Ok(())
-    }
-
-    #[inline]
-    fn instruction_0xf(&mut self, instruction: Instruction) -> Result<(), EmuErr> {
-        let x_reg_val = self.v_reg[instruction.x()];
-        match instruction.kk() {
-            0x7 => {
-                self.v_reg[instruction.x()] = self.delay_reg;
+            LoadI { nnn } => self.i_reg = nnn,
+            JumpOffset { nnn } => {
+                //BXNN, the register is picked from the high nibble of the address.
+                let offset_reg = if self.quirks.jumping_quirk {
+                    self.v_reg[(nnn >> 8) as usize]
+                } else {
+                    self.v_reg[0x0]
+                };
+                self.pc = nnn + offset_reg as u16;
+            }
+            Random { x, kk } => self.v_reg[x] = self.rng.next_byte() & kk,
+            Draw { x, y, n } => {
+                self.draw(x, y, n)?;
+                self.waiting_for_vblank =
+                    self.quirks.display_wait_quirk || self.timing == Timing::CosmacVip;
+            }
+            SkipKey { x } => {
+                if self.pressed_keys[self.v_reg[x] as usize] {
+                    self.skip_next();
+                }
+            }
+            SkipNotKey { x } => {
+                if !self.pressed_keys[self.v_reg[x] as usize] {
+                    self.skip_next();
+                }
+            }
+            LoadILong => {
+                //F000 NNNN, loads I with the 16 bit word following the instruction.
+                self.i_reg = self.fetch_word(self.pc)?;
+                self.pc = self.pc.wrapping_add(2);
             }
-            0xA => {
+            Plane { n } => self.selected_planes = n & 0x3,
+            Audio => {
+                self.check_ireg_offset(AUDIO_PATTERN_LEN as u16)?;
+                self.record_read(AUDIO_PATTERN_LEN);
+                let mut pattern = [0u8; AUDIO_PATTERN_LEN];
+                pattern.insert_slice(&self.memory[self.i_reg as usize..]);
+                self.audio_pattern.set_pattern(&pattern);
+            }
+            GetDelay { x } => self.v_reg[x] = self.delay_reg,
+            WaitKey { .. } => {
                 if !self.pressed_keys.contains(&true) {
                     self.pc -= 2;
                 }
             }
-            0x15 => self.delay_reg = x_reg_val,
-            0x18 => {
-                //Instruction for sound delay.
-            }
-            0x1E => {
-                let x_reg_val = x_reg_val as u16;
+            SetDelay { x } => self.delay_reg = self.v_reg[x],
+            SetSound { x } => self.sound_reg = self.v_reg[x],
+            AddI { x } => {
+                let x_reg_val = self.v_reg[x] as u16;
                 self.check_ireg_offset(x_reg_val)?;
                 self.i_reg += x_reg_val;
             }
-            0x29 => self.i_reg = (x_reg_val * 5) as u16,
-            0x30 => self.i_reg = (0x50 + x_reg_val * 10) as u16,
-            0x33 => {
+            //Only the low nibble picks a glyph, larger values would overflow the multiply.
+            LoadFont { x } => self.i_reg = (self.v_reg[x] & 0xF) as u16 * 5,
+            LoadBigFont { x } => self.i_reg = 0x50 + (self.v_reg[x] & 0xF) as u16 * 10,
+            Bcd { x } => {
                 self.check_ireg_offset(2)?;
-                let bcd = u8_to_bcd_array(x_reg_val);
+                self.record_write(3)?;
+                let bcd = u8_to_bcd_array(self.v_reg[x]);
                 let mem_slice = &mut self.memory[self.i_reg as usize..];
                 mem_slice.insert_slice(&bcd);
             }
-            0x55 => {
-                let v_reg_slice = &self.v_reg[..=instruction.x()];
+            Pitch { x } => self.audio_pattern.set_pitch(self.v_reg[x]),
+            Store { x } => {
+                self.check_ireg_offset(x as u16 + 1)?;
+                self.record_write(x + 1)?;
+                let v_reg_slice = &self.v_reg[..=x];
                 let mem_slice = &mut self.memory[self.i_reg as usize..];
                 mem_slice.insert_slice(v_reg_slice);
-                if self.quirks.jumping_quirk {
-                    self.i_reg += 1;
+                if self.quirks.memory_quirk {
+                    self.inc_ireg_past_registers(x)?;
                 }
             }
-            0x65 => {
-                let v_reg_slice = &mut self.v_reg[..=instruction.x()];
+            Load { x } => {
+                self.check_ireg_offset(x as u16 + 1)?;
+                self.record_read(x + 1);
+                let v_reg_slice = &mut self.v_reg[..=x];
                 let mem_slice = &self.memory[self.i_reg as usize..];
-                v_reg_slice.insert_slice(&mem_slice);
-                if self.quirks.jumping_quirk {
-                    self.i_reg += 1;
+                v_reg_slice.insert_slice(mem_slice);
+                if self.quirks.memory_quirk {
+                    self.inc_ireg_past_registers(x)?;
                 }
             }
-            _ => {
-                return Err(EmuErr::BadInstruction {
-                    pc: self.pc,
-                    instruction,
-                })
+            SaveFlags { x } => {
+                let v_reg_slice = &self.v_reg[..=x];
+                self.rpl_flags.insert_slice(v_reg_slice);
+            }
+            LoadFlags { x } => {
+                let v_reg_slice = &mut self.v_reg[..=x];
+                v_reg_slice.insert_slice(&self.rpl_flags);
             }
         }
         Ok(())
     }
 
-    fn draw(&mut self, instruction: Instruction) -> Result<(), EmuErr> {
-        let v_reg_x = self.v_reg[instruction.x()] as usize;
-        let v_reg_y = self.v_reg[instruction.y()] as usize;
-        let i_reg = self.i_reg as usize;
+    //8XY1, 8XY2 and 8XY3 clear VF on the original interpreter.
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset_quirk {
+            self.v_reg[0xF] = 0;
+        }
+    }
+
+    fn draw(&mut self, x: usize, y: usize, n: u8) -> Result<(), EmuErr> {
+        let clipping = self.quirks.clipping_quirk;
         let mut vf_new = 0x0;
-        //Slice of memory that will be used to draw from.
-        let high_res_sprite = self.high_res && instruction.low_nibble() == 0;
-        let mem_slice = if high_res_sprite {
-            self.check_ireg_offset(0x20)?;
-            &self.memory[i_reg..][..0x20]
+        //XO-CHIP draws 16x16 sprites for N = 0 in low res as well.
+        let big_sprite = n == 0 && (self.high_res || self.platform.has_xo_chip_instructions());
+        let sprite_len = if big_sprite {
+            BIG_SPRITE_LEN
         } else {
-            self.check_ireg_offset(instruction.low_nibble() as u16)?;
-            &self.memory[i_reg..][..instruction.low_nibble() as usize]
+            n as usize
         };
-        let (mut x_wrap, mut y_wrap) = (DISPLAY_WIDTH, DISPLAY_HEIGHT);
-        if !self.high_res {
-            x_wrap /= 2;
-            y_wrap /= 2;
-        }
-        for (i, byte) in mem_slice.iter().enumerate() {
-            let y_offset = if high_res_sprite { i / 2 } else { i };
-            let y_coord = (v_reg_y + y_offset) % y_wrap;
-            for j in 0usize..8usize {
-                let x_offset = if high_res_sprite { j + 8 * (i % 2) } else { j };
-                let x_coord = (v_reg_x + x_offset) % x_wrap;
-                let curr_bit = (byte >> (7 - j)) & 0x1 == 0x1;
-
-                //continue if the current sprite bit is 0.
-                if !curr_bit {
+        //Each selected plane consumes its own sprite, stored one after the other from I.
+        let plane_count = self.selected_planes.count_ones() as usize;
+        self.check_ireg_offset((sprite_len * plane_count) as u16)?;
+        self.record_read(sprite_len * plane_count);
+        //Low res pixels are drawn as 2x2 blocks so the display buffer is always high res.
+        let scale = if self.high_res { 1 } else { 2 };
+        let (x_wrap, y_wrap) = (DISPLAY_WIDTH / scale, DISPLAY_HEIGHT / scale);
+        //The starting position always wraps, clipping only applies to the rest of the sprite.
+        let v_reg_x = self.v_reg[x] as usize % x_wrap;
+        let v_reg_y = self.v_reg[y] as usize % y_wrap;
+        let mut sprite_addr = self.i_reg as usize;
+        for plane in self.selected_plane_indices() {
+            //Slice of memory that will be used to draw from.
+            let mem_slice = &self.memory[sprite_addr..][..sprite_len];
+            sprite_addr += sprite_len;
+            let display_buffer = &mut self.display_planes[plane];
+            for (i, byte) in mem_slice.iter().enumerate() {
+                let y_offset = if big_sprite { i / 2 } else { i };
+                let y_coord = v_reg_y + y_offset;
+                if clipping && y_coord >= y_wrap {
                     continue;
                 }
+                let y_coord = y_coord % y_wrap;
+                for j in 0usize..8usize {
+                    let x_offset = if big_sprite { j + 8 * (i % 2) } else { j };
+                    let x_coord = v_reg_x + x_offset;
+                    let curr_bit = (byte >> (7 - j)) & 0x1 == 0x1;
 
-                let pixel = self.display_buffer[x_coord][y_coord];
+                    //continue if the current sprite bit is 0 or clipped off screen.
+                    if !curr_bit || (clipping && x_coord >= x_wrap) {
+                        continue;
+                    }
+                    let x_coord = x_coord % x_wrap;
 
-                //VF flag set true if there is a pixel collision (1 XOR 1).
-                if pixel {
-                    vf_new = 0x1;
-                }
+                    for x_pixel in x_coord * scale..(x_coord + 1) * scale {
+                        for y_pixel in y_coord * scale..(y_coord + 1) * scale {
+                            let pixel = display_buffer[x_pixel][y_pixel];
 
-                //Negate pixel as pixel is XORed onto display
-                self.display_buffer[x_coord].set(y_coord, !pixel);
+                            //VF flag set true if there is a pixel collision (1 XOR 1).
+                            if pixel {
+                                vf_new = 0x1;
+                            }
+
+                            //Negate pixel as pixel is XORed onto display
+                            display_buffer[x_pixel].set(y_pixel, !pixel);
+                        }
+                    }
+                }
             }
         }
         self.v_reg[0xF] = vf_new;
         Ok(())
     }
 
+    //Indices of the planes selected by FN01, in drawing order.
+    fn selected_plane_indices(&self) -> impl Iterator<Item = usize> {
+        let selected_planes = self.selected_planes;
+        (0..self.display_planes.len()).filter(move |plane| selected_planes & (1 << plane) != 0)
+    }
+
+    //Scroll distances are in display pixels, which are twice the size in low res
+    //unless the half scroll quirk is on.
+    fn scroll_distance(&self, distance: usize) -> usize {
+        if self.high_res || self.quirks.half_scroll_quirk {
+            distance
+        } else {
+            distance * 2
+        }
+    }
+
+    fn scroll_down(&mut self, distance: usize) {
+        let distance = self.scroll_distance(distance);
+        for plane in self.selected_plane_indices() {
+            for col in self.display_planes[plane].iter_mut() {
+                for y in (0..DISPLAY_HEIGHT).rev() {
+                    let pixel = y >= distance && col[y - distance];
+                    col.set(y, pixel);
+                }
+            }
+        }
+    }
+
+    fn scroll_up(&mut self, distance: usize) {
+        let distance = self.scroll_distance(distance);
+        for plane in self.selected_plane_indices() {
+            for col in self.display_planes[plane].iter_mut() {
+                for y in 0..DISPLAY_HEIGHT {
+                    let pixel = y + distance < DISPLAY_HEIGHT && col[y + distance];
+                    col.set(y, pixel);
+                }
+            }
+        }
+    }
+
+    fn scroll_right(&mut self, distance: usize) {
+        let distance = self.scroll_distance(distance);
+        for plane in self.selected_plane_indices() {
+            let display_buffer = &mut self.display_planes[plane];
+            display_buffer.rotate_right(distance);
+            for col in display_buffer[..distance].iter_mut() {
+                col.clear();
+            }
+        }
+    }
+
+    fn scroll_left(&mut self, distance: usize) {
+        let distance = self.scroll_distance(distance);
+        for plane in self.selected_plane_indices() {
+            let display_buffer = &mut self.display_planes[plane];
+            display_buffer.rotate_left(distance);
+            for col in display_buffer[DISPLAY_WIDTH - distance..].iter_mut() {
+                col.clear();
+            }
+        }
+    }
+
     fn clear_display(&mut self) {
-        for col in self.display_buffer.iter_mut() {
-            col.clear();
+        for plane in self.selected_plane_indices() {
+            for col in self.display_planes[plane].iter_mut() {
+                col.clear();
+            }
         }
     }
 
+    //The first plane, which is the whole display outside of XO-CHIP.
     pub fn get_display_buffer(&self) -> &[BitVec] {
-        &self.display_buffer
+        &self.display_planes[0]
+    }
+
+    pub fn get_display_plane(&self, plane: usize) -> &[BitVec] {
+        &self.display_planes[plane]
+    }
+
+    //Colour index 0-3 of a pixel, with bit n set if the pixel is lit in plane n.
+    pub fn get_pixel(&self, x: usize, y: usize) -> usize {
+        self.display_planes
+            .iter()
+            .enumerate()
+            .map(|(plane, display_buffer)| (display_buffer[x][y] as usize) << plane)
+            .sum()
     }
 
     pub fn set_key(&mut self, key: usize) {
@@ -419,15 +824,289 @@ impl Chip8 {
         self.pressed_keys[key] = false;
     }
 
-    pub fn dec_delay_reg(&mut self) {
-        if self.delay_reg > 0 {
-            self.delay_reg -= 1;
+    //Should be called at 60Hz, counts down both the delay and sound timers.
+    pub fn dec_timers(&mut self) {
+        self.delay_reg = self.delay_reg.saturating_sub(1);
+        self.sound_reg = self.sound_reg.saturating_sub(1);
+        self.waiting_for_vblank = false;
+    }
+
+    //The buzzer sounds for as long as the sound timer is non-zero.
+    pub fn is_buzzer_active(&self) -> bool {
+        self.sound_reg > 0
+    }
+
+    pub fn get_audio_pattern(&self) -> &AudioPattern {
+        &self.audio_pattern
+    }
+
+    //Renders the XO-CHIP audio pattern while the buzzer is active, silence otherwise.
+    pub fn fill_audio(&mut self, out: &mut [f32], sample_rate: u32) {
+        if self.is_buzzer_active() {
+            self.audio_pattern.fill_audio(out, sample_rate);
+        } else {
+            out.fill(0.0);
         }
     }
 
+    pub fn get_platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn get_tickrate(&self) -> u32 {
+        self.tickrate
+    }
+
+    pub fn set_tickrate(&mut self, tickrate: u32) {
+        self.tickrate = tickrate;
+    }
+
+    pub fn get_timing(&self) -> Timing {
+        self.timing
+    }
+
+    //Total cost of the instructions executed so far, see Timing.
+    pub fn get_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_tracer(&mut self, mut tracer: Option<Tracer>) {
+        if let Some(tracer) = tracer.as_mut() {
+            tracer.start(self.platform);
+        }
+        self.tracer = tracer;
+    }
+
+    //Detaches the tracer so it can be finished.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    pub fn get_memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn get_v_regs(&self) -> &[u8; 0x10] {
+        &self.v_reg
+    }
+
+    pub fn get_i_reg(&self) -> u16 {
+        self.i_reg
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn get_delay_reg(&self) -> u8 {
+        self.delay_reg
+    }
+
+    pub fn get_sound_reg(&self) -> u8 {
+        self.sound_reg
+    }
+
+    //Return addresses of the active subroutine calls, outermost first.
+    pub fn get_stack(&self) -> &[u16] {
+        self.stack.as_slice()
+    }
+
+    //Setters for debuggers that edit the machine while it is stopped.
+    pub fn get_memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    pub fn set_v_reg(&mut self, reg: usize, val: u8) {
+        self.v_reg[reg] = val;
+    }
+
+    pub fn set_i_reg(&mut self, val: u16) {
+        self.i_reg = val;
+    }
+
+    pub fn set_pc(&mut self, val: u16) {
+        self.pc = val;
+    }
+
+    pub fn set_delay_reg(&mut self, val: u8) {
+        self.delay_reg = val;
+    }
+
+    pub fn set_sound_reg(&mut self, val: u8) {
+        self.sound_reg = val;
+    }
+
+    //Memory read or written by the last instruction, watched by the debugger.
+    pub fn get_mem_accesses(&self) -> &[MemAccess] {
+        &self.mem_accesses
+    }
+
     pub fn is_high_res(&self) -> bool {
         self.high_res
     }
+
+    //True once the program has exited through 00FD.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    //Snapshot of the whole machine in a versioned, checksummed binary format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.u8(match self.platform {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        });
+        let quirks = self.quirks;
+        for quirk in [
+            quirks.vf_reset_quirk,
+            quirks.memory_quirk,
+            quirks.display_wait_quirk,
+            quirks.clipping_quirk,
+            quirks.shifting_quirk,
+            quirks.jumping_quirk,
+            quirks.half_scroll_quirk,
+        ] {
+            writer.bool(quirk);
+        }
+        writer.u32(self.memory.len() as u32);
+        writer.bytes(&self.memory);
+        writer.bytes(&self.v_reg);
+        writer.u16(self.i_reg);
+        writer.u8(self.delay_reg);
+        writer.u8(self.sound_reg);
+        writer.u16(self.pc);
+        let stack = self.stack.as_slice();
+        writer.u8(stack.len() as u8);
+        for entry in stack {
+            writer.u16(*entry);
+        }
+        let pressed_keys = (0..self.pressed_keys.len())
+            .filter(|key| self.pressed_keys[*key])
+            .fold(0u16, |keys, key| keys | 1 << key);
+        writer.u16(pressed_keys);
+        for display_buffer in self.display_planes.iter() {
+            for col in display_buffer.iter() {
+                writer.bytes(&col.to_bytes());
+            }
+        }
+        writer.u8(self.selected_planes);
+        writer.bool(self.high_res);
+        writer.bytes(&self.rpl_flags);
+        writer.bool(self.halted);
+        writer.bool(self.waiting_for_vblank);
+        writer.bytes(self.audio_pattern.pattern());
+        writer.u8(self.audio_pattern.pitch());
+        let rng_state = self.rng.state();
+        writer.bool(rng_state.is_some());
+        writer.u64(rng_state.unwrap_or(0));
+        writer.u64(self.frame_budget as u64);
+        writer.u64(self.cycles);
+        writer.u32(self.tickrate);
+        writer.u8(match self.timing {
+            Timing::Tickrate => 0,
+            Timing::CosmacVip => 1,
+        });
+        writer.finish()
+    }
+
+    //Restores a snapshot from save_state, leaving the machine untouched if it is invalid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), EmuErr> {
+        let mut reader = StateReader::new(data)?;
+        let platform = match reader.u8()? {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            _ => return Err(bad_state("unknown platform")),
+        };
+        let quirks = Quirks {
+            vf_reset_quirk: reader.bool()?,
+            memory_quirk: reader.bool()?,
+            display_wait_quirk: reader.bool()?,
+            clipping_quirk: reader.bool()?,
+            shifting_quirk: reader.bool()?,
+            jumping_quirk: reader.bool()?,
+            half_scroll_quirk: reader.bool()?,
+        };
+        let mem_len = reader.u32()? as usize;
+        if mem_len != platform.memory_size() {
+            return Err(bad_state("memory size doesn't match platform"));
+        }
+        let memory = reader.bytes(mem_len)?;
+        let v_reg = reader.bytes(0x10)?;
+        let i_reg = reader.u16()?;
+        let delay_reg = reader.u8()?;
+        let sound_reg = reader.u8()?;
+        let pc = reader.u16()?;
+        let stack_len = reader.u8()? as usize;
+        let stack_entries = (0..stack_len)
+            .map(|_| reader.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+        let stack = Stack::from_slice(&stack_entries).map_err(|_| bad_state("stack too deep"))?;
+        let pressed_keys = reader.u16()?;
+        let col_len = DISPLAY_HEIGHT / 8;
+        let mut display_planes = self.display_planes.clone();
+        for display_buffer in display_planes.iter_mut() {
+            for col in display_buffer.iter_mut() {
+                *col = BitVec::from_bytes(reader.bytes(col_len)?);
+            }
+        }
+        let selected_planes = reader.u8()?;
+        let high_res = reader.bool()?;
+        let rpl_flags = reader.bytes(0x10)?;
+        let halted = reader.bool()?;
+        let waiting_for_vblank = reader.bool()?;
+        let mut audio_pattern = AudioPattern::new();
+        let mut pattern = [0u8; AUDIO_PATTERN_LEN];
+        pattern.insert_slice(reader.bytes(AUDIO_PATTERN_LEN)?);
+        audio_pattern.set_pattern(&pattern);
+        audio_pattern.set_pitch(reader.u8()?);
+        let has_rng_state = reader.bool()?;
+        let rng_state = reader.u64()?;
+        let frame_budget = reader.u64()? as i64;
+        let cycles = reader.u64()?;
+        let tickrate = reader.u32()?;
+        let timing = match reader.u8()? {
+            0 => Timing::Tickrate,
+            1 => Timing::CosmacVip,
+            _ => return Err(bad_state("unknown timing")),
+        };
+        reader.finish()?;
+
+        self.platform = platform;
+        self.quirks = quirks;
+        self.memory = memory.into();
+        self.v_reg.insert_slice(v_reg);
+        self.i_reg = i_reg;
+        self.delay_reg = delay_reg;
+        self.sound_reg = sound_reg;
+        self.pc = pc;
+        self.stack = stack;
+        for (key, pressed) in self.pressed_keys.iter_mut().enumerate() {
+            *pressed = pressed_keys & (1 << key) != 0;
+        }
+        self.display_planes = display_planes;
+        self.selected_planes = selected_planes;
+        self.high_res = high_res;
+        self.rpl_flags.insert_slice(rpl_flags);
+        self.halted = halted;
+        self.waiting_for_vblank = waiting_for_vblank;
+        self.audio_pattern = audio_pattern;
+        if has_rng_state {
+            self.rng.set_state(rng_state);
+        }
+        self.frame_budget = frame_budget;
+        self.cycles = cycles;
+        self.tickrate = tickrate;
+        self.timing = timing;
+        self.mem_accesses.clear();
+        Ok(())
+    }
 }
 
 fn u8_to_bcd_array(num: u8) -> [u8; 3] {
@@ -441,3 +1120,80 @@ fn u8_to_bcd_array(num: u8) -> [u8; 3] {
     }
     output
 }
+
+//Registers X to Y inclusive, in descending order if X > Y.
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Draws random digits down the screen, setting the delay timer to each.
+    const PROGRAM: [u8; 12] = [
+        0xC0, 0x0F, 0xF0, 0x29, 0xD0, 0x15, 0x71, 0x01, 0xF0, 0x15, 0x12, 0x00,
+    ];
+
+    fn build(timing: Timing, tickrate: u32) -> Chip8 {
+        Chip8Builder::from_preset("xo-chip")
+            .expect("Known preset")
+            .with_seed(7)
+            .with_timing(timing)
+            .with_tickrate(tickrate)
+            .with_program(&PROGRAM)
+            .build()
+            .expect("Program fits in memory")
+    }
+
+    fn run_frames(chip_8: &mut Chip8, frames: u32) {
+        for _ in 0..frames {
+            chip_8
+                .run_frame()
+                .expect("Program only uses valid instructions");
+        }
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut original = build(Timing::CosmacVip, DEFAULT_TICKRATE);
+        run_frames(&mut original, 5);
+        let state = original.save_state();
+
+        let mut restored = build(Timing::Tickrate, 1);
+        restored
+            .load_state(&state)
+            .expect("State from save_state loads");
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.get_cycles(), original.get_cycles());
+        assert_eq!(restored.get_tickrate(), DEFAULT_TICKRATE);
+
+        //Both carry on identically, random numbers and frame timing included.
+        run_frames(&mut original, 5);
+        run_frames(&mut restored, 5);
+        assert_eq!(restored.save_state(), original.save_state());
+    }
+
+    #[test]
+    fn load_state_rejects_corruption() {
+        let mut chip_8 = build(Timing::Tickrate, DEFAULT_TICKRATE);
+        run_frames(&mut chip_8, 2);
+        let mut state = chip_8.save_state();
+        let before = build(Timing::Tickrate, DEFAULT_TICKRATE).save_state();
+        let mid = state.len() / 2;
+        state[mid] ^= 0x01;
+
+        let mut target = build(Timing::Tickrate, DEFAULT_TICKRATE);
+        assert!(matches!(
+            target.load_state(&state),
+            Err(EmuErr::BadSaveState {
+                reason: "checksum mismatch"
+            })
+        ));
+        assert_eq!(target.save_state(), before);
+    }
+}
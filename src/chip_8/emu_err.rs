@@ -1,14 +1,18 @@
-use super::instruction::Instruction;
+use super::{instruction::Instruction, Opcode, Platform};
 use std::fmt;
 
 #[derive(Debug)]
 pub enum EmuErr {
     ProgramLength { pg_len: usize, max_len: usize },
     BadInstruction { pc: u16, instruction: Instruction },
+    UnknownOpcode { opcode: u16, platform: Platform },
+    OperandOutOfRange { opcode: Opcode },
     PcOutOfBounds { pc: u16 },
     StackUnderflow { sp: usize },
     StackOverflow { sp: usize },
     IregOverflow { ireg: u16, offset: u16 },
+    BadSaveState { reason: &'static str },
+    ProtectedWrite { pc: u16, addr: u16 },
 }
 
 impl fmt::Display for EmuErr {
@@ -25,6 +29,12 @@ impl fmt::Display for EmuErr {
             BadInstruction { pc, instruction } => {
                 write!(f, "Bad instruction {instruction} at location {:#04x}", pc)
             }
+            UnknownOpcode { opcode, platform } => {
+                write!(f, "Opcode {:#06x} doesn't exist on {:?}", opcode, platform)
+            }
+            OperandOutOfRange { opcode } => {
+                write!(f, "Operand out of range in {:?}", opcode)
+            }
             PcOutOfBounds { pc } => {
                 write!(f, "pc out of bounds at {:#04x}", pc)
             }
@@ -37,6 +47,16 @@ impl fmt::Display for EmuErr {
             IregOverflow { ireg, offset } => {
                 write!(f, "Attempted to add {:#04x} to ireg {:#04x}", offset, ireg)
             }
+            BadSaveState { reason } => {
+                write!(f, "Could not load save state: {reason}")
+            }
+            ProtectedWrite { pc, addr } => {
+                write!(
+                    f,
+                    "Write to protected memory {:#04x} at location {:#04x}",
+                    addr, pc
+                )
+            }
         }
     }
 }
@@ -0,0 +1,21 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+//A range of memory read or written by a single instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+    pub addr: u16,
+    pub len: u16,
+    pub kind: AccessKind,
+}
+
+impl MemAccess {
+    //True if any byte of the access falls within start..=end.
+    pub fn overlaps(&self, start: u16, end: u16) -> bool {
+        let last = self.addr as u32 + self.len.max(1) as u32 - 1;
+        self.addr <= end && last >= start as u32
+    }
+}
@@ -1,9 +1,24 @@
 mod chip_8_emulator;
 pub use chip_8_emulator::*;
 mod instruction;
-use instruction::Instruction;
+pub use instruction::Instruction;
+mod opcode;
+pub use opcode::{Opcode, OpcodeClass};
+mod audio_pattern;
+pub use audio_pattern::{AudioPattern, AUDIO_PATTERN_LEN};
+mod mem_access;
+pub use mem_access::{AccessKind, MemAccess};
 mod emu_err;
 pub use emu_err::EmuErr;
 pub mod insert_slice;
+mod platform;
+pub use platform::Platform;
+mod quirks;
+pub use quirks::Quirks;
+mod rng;
+pub use rng::{Rng, XorShift};
+mod save_state;
 mod stack;
+mod timing;
 use stack::*;
+pub use timing::Timing;
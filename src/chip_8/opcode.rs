@@ -0,0 +1,463 @@
+use super::{EmuErr, Instruction, Platform};
+use std::str::FromStr;
+
+//A decoded instruction, register operands are indices into the V registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    //00CN
+    ScrollDown { n: u8 },
+    //00DN
+    ScrollUp { n: u8 },
+    //00E0
+    Clear,
+    //00EE
+    Return,
+    //00FB
+    ScrollRight,
+    //00FC
+    ScrollLeft,
+    //00FD
+    Exit,
+    //00FE
+    LowRes,
+    //00FF
+    HighRes,
+    //1NNN
+    Jump { nnn: u16 },
+    //2NNN
+    Call { nnn: u16 },
+    //3XNN
+    SkipEqImm { x: usize, kk: u8 },
+    //4XNN
+    SkipNeImm { x: usize, kk: u8 },
+    //5XY0
+    SkipEqReg { x: usize, y: usize },
+    //5XY2
+    SaveRange { x: usize, y: usize },
+    //5XY3
+    LoadRange { x: usize, y: usize },
+    //6XNN
+    LoadImm { x: usize, kk: u8 },
+    //7XNN
+    AddImm { x: usize, kk: u8 },
+    //8XY0
+    Move { x: usize, y: usize },
+    //8XY1
+    Or { x: usize, y: usize },
+    //8XY2
+    And { x: usize, y: usize },
+    //8XY3
+    Xor { x: usize, y: usize },
+    //8XY4
+    AddReg { x: usize, y: usize },
+    //8XY5
+    Sub { x: usize, y: usize },
+    //8XY6
+    ShiftRight { x: usize, y: usize },
+    //8XY7
+    SubReverse { x: usize, y: usize },
+    //8XYE
+    ShiftLeft { x: usize, y: usize },
+    //9XY0
+    SkipNeReg { x: usize, y: usize },
+    //ANNN
+    LoadI { nnn: u16 },
+    //BNNN, or BXNN with the jumping quirk.
+    JumpOffset { nnn: u16 },
+    //CXNN
+    Random { x: usize, kk: u8 },
+    //DXYN
+    Draw { x: usize, y: usize, n: u8 },
+    //EX9E
+    SkipKey { x: usize },
+    //EXA1
+    SkipNotKey { x: usize },
+    //F000, followed by the 16 bit address loaded into I.
+    LoadILong,
+    //FN01
+    Plane { n: u8 },
+    //F002
+    Audio,
+    //FX07
+    GetDelay { x: usize },
+    //FX0A
+    WaitKey { x: usize },
+    //FX15
+    SetDelay { x: usize },
+    //FX18
+    SetSound { x: usize },
+    //FX1E
+    AddI { x: usize },
+    //FX29
+    LoadFont { x: usize },
+    //FX30
+    LoadBigFont { x: usize },
+    //FX33
+    Bcd { x: usize },
+    //FX3A
+    Pitch { x: usize },
+    //FX55
+    Store { x: usize },
+    //FX65
+    Load { x: usize },
+    //FX75
+    SaveFlags { x: usize },
+    //FX85
+    LoadFlags { x: usize },
+}
+
+//Broad groups of instructions, used to filter traces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpcodeClass {
+    //Jumps, calls, returns and exit.
+    Flow,
+    //Conditional skips, key tests included.
+    Skip,
+    //Register loads and arithmetic, CXNN included.
+    Alu,
+    //I and memory.
+    Memory,
+    //Drawing, scrolling and resolution or plane changes.
+    Display,
+    //Timers, waiting for keys and sound.
+    Io,
+}
+
+impl FromStr for OpcodeClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flow" => Ok(OpcodeClass::Flow),
+            "skip" => Ok(OpcodeClass::Skip),
+            "alu" => Ok(OpcodeClass::Alu),
+            "memory" => Ok(OpcodeClass::Memory),
+            "display" => Ok(OpcodeClass::Display),
+            "io" => Ok(OpcodeClass::Io),
+            _ => Err(format!("Unknown opcode class {s}")),
+        }
+    }
+}
+
+impl Opcode {
+    //Decodes a word, rejecting opcodes the platform doesn't have.
+    pub fn decode(word: u16, platform: Platform) -> Result<Self, EmuErr> {
+        use Opcode::*;
+        let instruction = Instruction::new(word);
+        let (x, y) = (instruction.x(), instruction.y());
+        let (n, kk, nnn) = (
+            instruction.low_nibble(),
+            instruction.kk(),
+            instruction.nnn(),
+        );
+        let schip = platform.has_schip_instructions();
+        let xo_chip = platform.has_xo_chip_instructions();
+        let opcode = match instruction.high_nibble() {
+            0x0 if x == 0 => match kk {
+                0xC0..=0xCF if schip => Some(ScrollDown { n }),
+                0xD0..=0xDF if xo_chip => Some(ScrollUp { n }),
+                0xE0 => Some(Clear),
+                0xEE => Some(Return),
+                0xFB if schip => Some(ScrollRight),
+                0xFC if schip => Some(ScrollLeft),
+                0xFD if schip => Some(Exit),
+                0xFE if schip => Some(LowRes),
+                0xFF if schip => Some(HighRes),
+                _ => None,
+            },
+            0x1 => Some(Jump { nnn }),
+            0x2 => Some(Call { nnn }),
+            0x3 => Some(SkipEqImm { x, kk }),
+            0x4 => Some(SkipNeImm { x, kk }),
+            0x5 => match n {
+                0x0 => Some(SkipEqReg { x, y }),
+                0x2 if xo_chip => Some(SaveRange { x, y }),
+                0x3 if xo_chip => Some(LoadRange { x, y }),
+                _ => None,
+            },
+            0x6 => Some(LoadImm { x, kk }),
+            0x7 => Some(AddImm { x, kk }),
+            0x8 => match n {
+                0x0 => Some(Move { x, y }),
+                0x1 => Some(Or { x, y }),
+                0x2 => Some(And { x, y }),
+                0x3 => Some(Xor { x, y }),
+                0x4 => Some(AddReg { x, y }),
+                0x5 => Some(Sub { x, y }),
+                0x6 => Some(ShiftRight { x, y }),
+                0x7 => Some(SubReverse { x, y }),
+                0xE => Some(ShiftLeft { x, y }),
+                _ => None,
+            },
+            0x9 if n == 0 => Some(SkipNeReg { x, y }),
+            0xA => Some(LoadI { nnn }),
+            0xB => Some(JumpOffset { nnn }),
+            0xC => Some(Random { x, kk }),
+            0xD => Some(Draw { x, y, n }),
+            0xE => match kk {
+                0x9E => Some(SkipKey { x }),
+                0xA1 => Some(SkipNotKey { x }),
+                _ => None,
+            },
+            0xF => match kk {
+                0x00 if xo_chip && x == 0 => Some(LoadILong),
+                0x01 if xo_chip => Some(Plane { n: x as u8 }),
+                0x02 if xo_chip && x == 0 => Some(Audio),
+                0x07 => Some(GetDelay { x }),
+                0x0A => Some(WaitKey { x }),
+                0x15 => Some(SetDelay { x }),
+                0x18 => Some(SetSound { x }),
+                0x1E => Some(AddI { x }),
+                0x29 => Some(LoadFont { x }),
+                0x30 if schip => Some(LoadBigFont { x }),
+                0x33 => Some(Bcd { x }),
+                0x3A if xo_chip => Some(Pitch { x }),
+                0x55 => Some(Store { x }),
+                0x65 => Some(Load { x }),
+                0x75 if schip => Some(SaveFlags { x }),
+                0x85 if schip => Some(LoadFlags { x }),
+                _ => None,
+            },
+            _ => None,
+        };
+        opcode.ok_or(EmuErr::UnknownOpcode {
+            opcode: word,
+            platform,
+        })
+    }
+
+    //Inverse of decode, fails if an operand doesn't fit its field.
+    pub fn encode(self) -> Result<u16, EmuErr> {
+        use Opcode::*;
+        if !self.operands_fit() {
+            return Err(EmuErr::OperandOutOfRange { opcode: self });
+        }
+        let xy = |base: u16, x: usize, y: usize| base | (x as u16) << 8 | (y as u16) << 4;
+        let xkk = |base: u16, x: usize, kk: u8| base | (x as u16) << 8 | kk as u16;
+        let fx = |kk: u16, x: usize| 0xF000 | (x as u16) << 8 | kk;
+        let word = match self {
+            ScrollDown { n } => 0x00C0 | n as u16,
+            ScrollUp { n } => 0x00D0 | n as u16,
+            Clear => 0x00E0,
+            Return => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowRes => 0x00FE,
+            HighRes => 0x00FF,
+            Jump { nnn } => 0x1000 | nnn,
+            Call { nnn } => 0x2000 | nnn,
+            SkipEqImm { x, kk } => xkk(0x3000, x, kk),
+            SkipNeImm { x, kk } => xkk(0x4000, x, kk),
+            SkipEqReg { x, y } => xy(0x5000, x, y),
+            SaveRange { x, y } => xy(0x5002, x, y),
+            LoadRange { x, y } => xy(0x5003, x, y),
+            LoadImm { x, kk } => xkk(0x6000, x, kk),
+            AddImm { x, kk } => xkk(0x7000, x, kk),
+            Move { x, y } => xy(0x8000, x, y),
+            Or { x, y } => xy(0x8001, x, y),
+            And { x, y } => xy(0x8002, x, y),
+            Xor { x, y } => xy(0x8003, x, y),
+            AddReg { x, y } => xy(0x8004, x, y),
+            Sub { x, y } => xy(0x8005, x, y),
+            ShiftRight { x, y } => xy(0x8006, x, y),
+            SubReverse { x, y } => xy(0x8007, x, y),
+            ShiftLeft { x, y } => xy(0x800E, x, y),
+            SkipNeReg { x, y } => xy(0x9000, x, y),
+            LoadI { nnn } => 0xA000 | nnn,
+            JumpOffset { nnn } => 0xB000 | nnn,
+            Random { x, kk } => xkk(0xC000, x, kk),
+            Draw { x, y, n } => xy(0xD000, x, y) | n as u16,
+            SkipKey { x } => xkk(0xE09E, x, 0),
+            SkipNotKey { x } => xkk(0xE0A1, x, 0),
+            LoadILong => 0xF000,
+            Plane { n } => fx(0x01, n as usize),
+            Audio => 0xF002,
+            GetDelay { x } => fx(0x07, x),
+            WaitKey { x } => fx(0x0A, x),
+            SetDelay { x } => fx(0x15, x),
+            SetSound { x } => fx(0x18, x),
+            AddI { x } => fx(0x1E, x),
+            LoadFont { x } => fx(0x29, x),
+            LoadBigFont { x } => fx(0x30, x),
+            Bcd { x } => fx(0x33, x),
+            Pitch { x } => fx(0x3A, x),
+            Store { x } => fx(0x55, x),
+            Load { x } => fx(0x65, x),
+            SaveFlags { x } => fx(0x75, x),
+            LoadFlags { x } => fx(0x85, x),
+        };
+        Ok(word)
+    }
+
+    //Registers and N are 4 bit fields, addresses 12 bit.
+    fn operands_fit(self) -> bool {
+        use Opcode::*;
+        match self {
+            Clear | Return | ScrollRight | ScrollLeft | Exit | LowRes | HighRes | LoadILong
+            | Audio => true,
+            ScrollDown { n } | ScrollUp { n } | Plane { n } => n <= 0xF,
+            Jump { nnn } | Call { nnn } | LoadI { nnn } | JumpOffset { nnn } => nnn <= 0xFFF,
+            SkipEqImm { x, .. }
+            | SkipNeImm { x, .. }
+            | LoadImm { x, .. }
+            | AddImm { x, .. }
+            | Random { x, .. }
+            | SkipKey { x }
+            | SkipNotKey { x }
+            | GetDelay { x }
+            | WaitKey { x }
+            | SetDelay { x }
+            | SetSound { x }
+            | AddI { x }
+            | LoadFont { x }
+            | LoadBigFont { x }
+            | Bcd { x }
+            | Pitch { x }
+            | Store { x }
+            | Load { x }
+            | SaveFlags { x }
+            | LoadFlags { x } => x <= 0xF,
+            SkipEqReg { x, y }
+            | SaveRange { x, y }
+            | LoadRange { x, y }
+            | Move { x, y }
+            | Or { x, y }
+            | And { x, y }
+            | Xor { x, y }
+            | AddReg { x, y }
+            | Sub { x, y }
+            | ShiftRight { x, y }
+            | SubReverse { x, y }
+            | ShiftLeft { x, y }
+            | SkipNeReg { x, y } => x <= 0xF && y <= 0xF,
+            Draw { x, y, n } => x <= 0xF && y <= 0xF && n <= 0xF,
+        }
+    }
+
+    pub fn class(self) -> OpcodeClass {
+        use Opcode::*;
+        match self {
+            Return | Exit | Jump { .. } | Call { .. } | JumpOffset { .. } => OpcodeClass::Flow,
+            SkipEqImm { .. }
+            | SkipNeImm { .. }
+            | SkipEqReg { .. }
+            | SkipNeReg { .. }
+            | SkipKey { .. }
+            | SkipNotKey { .. } => OpcodeClass::Skip,
+            LoadImm { .. }
+            | AddImm { .. }
+            | Move { .. }
+            | Or { .. }
+            | And { .. }
+            | Xor { .. }
+            | AddReg { .. }
+            | Sub { .. }
+            | ShiftRight { .. }
+            | SubReverse { .. }
+            | ShiftLeft { .. }
+            | Random { .. } => OpcodeClass::Alu,
+            SaveRange { .. }
+            | LoadRange { .. }
+            | LoadI { .. }
+            | LoadILong
+            | AddI { .. }
+            | LoadFont { .. }
+            | LoadBigFont { .. }
+            | Bcd { .. }
+            | Store { .. }
+            | Load { .. }
+            | SaveFlags { .. }
+            | LoadFlags { .. } => OpcodeClass::Memory,
+            ScrollDown { .. }
+            | ScrollUp { .. }
+            | Clear
+            | ScrollRight
+            | ScrollLeft
+            | LowRes
+            | HighRes
+            | Draw { .. }
+            | Plane { .. } => OpcodeClass::Display,
+            GetDelay { .. }
+            | WaitKey { .. }
+            | SetDelay { .. }
+            | SetSound { .. }
+            | Audio
+            | Pitch { .. } => OpcodeClass::Io,
+        }
+    }
+
+    //Length in bytes, F000 is followed by its address.
+    pub fn size(self) -> usize {
+        match self {
+            Opcode::LoadILong => 4,
+            _ => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashSet, mem};
+
+    const PLATFORMS: [Platform; 3] = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+    //Variants of Opcode, every one is decoded from some word on XO-CHIP.
+    const OPCODE_COUNT: usize = 50;
+
+    #[test]
+    fn decode_encode_round_trip() {
+        let mut forms = HashSet::new();
+        for platform in PLATFORMS {
+            for word in 0..=0xFFFF {
+                let Ok(opcode) = Opcode::decode(word, platform) else {
+                    continue;
+                };
+                forms.insert(mem::discriminant(&opcode));
+                let encoded = opcode.encode().expect("Decoded operands fit");
+                assert_eq!(encoded, word, "{opcode:?} on {platform:?}");
+                let decoded = Opcode::decode(encoded, platform).expect("Encoded opcode decodes");
+                assert_eq!(decoded, opcode, "{word:04x} on {platform:?}");
+            }
+        }
+        assert_eq!(forms.len(), OPCODE_COUNT);
+    }
+
+    #[test]
+    fn encode_rejects_out_of_range_operands() {
+        use Opcode::*;
+        for opcode in [
+            Jump { nnn: 0x1000 },
+            Call { nnn: 0xFFFF },
+            LoadI { nnn: 0x1000 },
+            JumpOffset { nnn: 0x1000 },
+            LoadImm { x: 0x10, kk: 0 },
+            Move { x: 0, y: 0x10 },
+            SkipNeReg { x: 0x10, y: 0 },
+            Draw {
+                x: 0,
+                y: 0,
+                n: 0x10,
+            },
+            ScrollDown { n: 0x10 },
+            Plane { n: 0x10 },
+            Store { x: 0x10 },
+        ] {
+            assert!(
+                matches!(opcode.encode(), Err(EmuErr::OperandOutOfRange { .. })),
+                "{opcode:?}"
+            );
+        }
+        assert_eq!(Jump { nnn: 0xFFF }.encode().unwrap(), 0x1FFF);
+        assert_eq!(
+            Draw {
+                x: 0xF,
+                y: 0xF,
+                n: 0xF
+            }
+            .encode()
+            .unwrap(),
+            0xDFFF
+        );
+    }
+}
@@ -0,0 +1,26 @@
+//The CHIP-8 variant being emulated, which decides the memory size and accepted instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    Chip8,
+    #[default]
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    //XO-CHIP is a superset of SCHIP so it also accepts its instructions.
+    pub fn has_schip_instructions(self) -> bool {
+        self != Platform::Chip8
+    }
+
+    pub fn has_xo_chip_instructions(self) -> bool {
+        self == Platform::XoChip
+    }
+}
@@ -0,0 +1,89 @@
+//Behavioural differences between CHIP-8 interpreters, the presets match the well known platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    //8XY1, 8XY2 and 8XY3 reset VF to 0.
+    pub vf_reset_quirk: bool,
+    //FX55 and FX65 increment I by X + 1, otherwise I is left unchanged.
+    pub memory_quirk: bool,
+    //DXYN waits for the next 60Hz tick, limiting drawing to one sprite per frame.
+    pub display_wait_quirk: bool,
+    //Sprites are clipped at the edges of the screen rather than wrapping around.
+    pub clipping_quirk: bool,
+    //8XY6 and 8XYE shift VX in place, ignoring VY.
+    pub shifting_quirk: bool,
+    //BNNN behaves as BXNN, jumping to XNN + VX rather than NNN + V0.
+    pub jumping_quirk: bool,
+    //Low res scrolling moves half a pixel per unit, as SCHIP 1.1 scrolls in high res pixels.
+    pub half_scroll_quirk: bool,
+}
+
+impl Quirks {
+    pub const fn cosmac_vip() -> Self {
+        Self {
+            vf_reset_quirk: true,
+            memory_quirk: true,
+            display_wait_quirk: true,
+            clipping_quirk: true,
+            shifting_quirk: false,
+            jumping_quirk: false,
+            half_scroll_quirk: false,
+        }
+    }
+
+    //SCHIP 1.1 as it ran on the HP48.
+    pub const fn schip_legacy() -> Self {
+        Self {
+            vf_reset_quirk: false,
+            memory_quirk: false,
+            display_wait_quirk: false,
+            clipping_quirk: true,
+            shifting_quirk: true,
+            jumping_quirk: true,
+            half_scroll_quirk: true,
+        }
+    }
+
+    //SCHIP as implemented by modern interpreters such as Octo.
+    pub const fn schip_modern() -> Self {
+        Self {
+            half_scroll_quirk: false,
+            ..Self::schip_legacy()
+        }
+    }
+
+    pub const fn xo_chip() -> Self {
+        Self {
+            vf_reset_quirk: false,
+            memory_quirk: true,
+            display_wait_quirk: false,
+            clipping_quirk: false,
+            shifting_quirk: false,
+            jumping_quirk: false,
+            half_scroll_quirk: false,
+        }
+    }
+
+    //Each quirk by the name used in movies and on the command line.
+    pub fn named_flags(&mut self) -> [(&'static str, &mut bool); 7] {
+        [
+            ("vf-reset", &mut self.vf_reset_quirk),
+            ("memory", &mut self.memory_quirk),
+            ("display-wait", &mut self.display_wait_quirk),
+            ("clipping", &mut self.clipping_quirk),
+            ("shifting", &mut self.shifting_quirk),
+            ("jumping", &mut self.jumping_quirk),
+            ("half-scroll", &mut self.half_scroll_quirk),
+        ]
+    }
+
+    //Turns the named quirk on or off.
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        let mut flags = self.named_flags();
+        let (_, flag) = flags
+            .iter_mut()
+            .find(|(known, _)| *known == name)
+            .ok_or_else(|| format!("Unknown quirk {name}"))?;
+        **flag = enabled;
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use rand::random;
+
+//Source of the random bytes used by CXNN, states that can be saved replay after a rewind or reload.
+pub trait Rng {
+    fn next_byte(&mut self) -> u8;
+
+    //Current state, None if it can't be saved.
+    fn state(&self) -> Option<u64> {
+        None
+    }
+
+    //Restores a state returned by state.
+    fn set_state(&mut self, _state: u64) {}
+}
+
+//Marsaglia's xorshift64, the same seed always gives the same sequence.
+#[derive(Debug, Clone)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.set_state(seed);
+        rng
+    }
+
+    //Seeded from the OS, for runs that don't need to be reproducible.
+    pub fn from_entropy() -> Self {
+        Self::new(random())
+    }
+}
+
+impl Rng for XorShift {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        //The high bits are the most random.
+        (self.state >> 56) as u8
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.state)
+    }
+
+    fn set_state(&mut self, state: u64) {
+        //Zero is a fixed point of xorshift, it would only ever produce zeros.
+        self.state = if state == 0 {
+            0x9E3779B97F4A7C15
+        } else {
+            state
+        };
+    }
+}
@@ -0,0 +1,133 @@
+use super::emu_err::EmuErr;
+
+//Identifies save state data, followed by the format version.
+pub const STATE_MAGIC: &[u8; 4] = b"C8ST";
+pub const STATE_VERSION: u8 = 1;
+
+//Appends little endian values to a save state, the CRC-32 is added by finish.
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut buffer = STATE_MAGIC.to_vec();
+        buffer.push(STATE_VERSION);
+        Self { buffer }
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.buffer.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.u8(val as u8);
+    }
+
+    pub fn u16(&mut self, val: u16) {
+        self.buffer.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.buffer.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64) {
+        self.buffer.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let checksum = crc32(&self.buffer);
+        self.u32(checksum);
+        self.buffer
+    }
+}
+
+//Reads back values in the order they were written, after checking the header and checksum.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, EmuErr> {
+        let header_len = STATE_MAGIC.len() + 1;
+        if data.len() < header_len + 4 {
+            return Err(bad_state("truncated"));
+        }
+        let (body, checksum) = data.split_at(data.len() - 4);
+        if crc32(body).to_le_bytes() != checksum {
+            return Err(bad_state("checksum mismatch"));
+        }
+        if &body[..STATE_MAGIC.len()] != STATE_MAGIC {
+            return Err(bad_state("not a save state"));
+        }
+        if body[STATE_MAGIC.len()] != STATE_VERSION {
+            return Err(bad_state("unsupported version"));
+        }
+        Ok(Self {
+            data: &body[header_len..],
+        })
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], EmuErr> {
+        if self.data.len() < len {
+            return Err(bad_state("truncated"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, EmuErr> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, EmuErr> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, EmuErr> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, EmuErr> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, EmuErr> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    //Fails if anything was left unread, which means the state doesn't match this build.
+    pub fn finish(self) -> Result<(), EmuErr> {
+        if !self.data.is_empty() {
+            return Err(bad_state("unexpected trailing data"));
+        }
+        Ok(())
+    }
+}
+
+pub fn bad_state(reason: &'static str) -> EmuErr {
+    EmuErr::BadSaveState { reason }
+}
+
+//CRC-32 (IEEE), computed bitwise as states are small.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 0x1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
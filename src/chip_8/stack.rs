@@ -24,6 +24,19 @@ impl Stack {
         Ok(())
     }
 
+    //Return addresses currently on the stack, oldest first.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn from_slice(entries: &[u16]) -> Result<Self, EmuErr> {
+        let mut stack = Self::new();
+        for entry in entries {
+            stack.push(*entry)?;
+        }
+        Ok(stack)
+    }
+
     pub fn pop(&mut self) -> Result<u16, EmuErr> {
         if self.sp == 0 {
             return Err(EmuErr::StackUnderflow { sp: self.sp });
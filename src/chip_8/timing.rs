@@ -0,0 +1,103 @@
+use super::Opcode;
+use std::{fmt, str::FromStr};
+
+//1802 machine cycles in a 60Hz frame, the VIP clock is 1.7609MHz at 8 clocks per cycle.
+const VIP_FRAME_CYCLES: u32 = 3668;
+//Cycles stolen by display DMA, one per byte and 8 bytes for each of the 128 lines shown.
+const VIP_DMA_CYCLES: u32 = 1024;
+//Cycles spent in the interrupt routine, which also counts down the timers.
+const VIP_INTERRUPT_CYCLES: u32 = 46;
+//Cycles of the interpreter loop fetching and dispatching each instruction.
+const VIP_FETCH_CYCLES: u32 = 40;
+
+//How much work each 60Hz frame does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    //A fixed number of instructions per frame, the tickrate.
+    #[default]
+    Tickrate,
+    //Instructions cost what they did on the COSMAC VIP, DXYN always waits for the interrupt.
+    CosmacVip,
+}
+
+impl Timing {
+    //Budget a frame starts with, spent by charging each instruction its cost.
+    pub(super) fn frame_budget(self, tickrate: u32) -> u32 {
+        match self {
+            Timing::Tickrate => tickrate,
+            Timing::CosmacVip => VIP_FRAME_CYCLES - VIP_DMA_CYCLES - VIP_INTERRUPT_CYCLES,
+        }
+    }
+
+    //Cost of an executed opcode, vx being VX before it ran and skipped whether it skipped.
+    pub(super) fn cost(self, opcode: Opcode, vx: u8, skipped: bool) -> u32 {
+        match self {
+            Timing::Tickrate => 1,
+            Timing::CosmacVip => VIP_FETCH_CYCLES + vip_cycles(opcode, vx, skipped),
+        }
+    }
+}
+
+//Approximate cycles the VIP interpreter spends executing opcode.
+fn vip_cycles(opcode: Opcode, vx: u8, skipped: bool) -> u32 {
+    use Opcode::*;
+    let skip = |cycles: u32| if skipped { cycles + 4 } else { cycles };
+    match opcode {
+        Clear => 680,
+        Return => 10,
+        Jump { .. } => 12,
+        Call { .. } => 26,
+        SkipEqImm { .. } | SkipNeImm { .. } => skip(10),
+        SkipEqReg { .. } | SkipNeReg { .. } | SkipKey { .. } | SkipNotKey { .. } => skip(14),
+        LoadImm { .. } => 6,
+        AddImm { .. } => 10,
+        Move { .. }
+        | Or { .. }
+        | And { .. }
+        | Xor { .. }
+        | AddReg { .. }
+        | Sub { .. }
+        | ShiftRight { .. }
+        | SubReverse { .. }
+        | ShiftLeft { .. } => 44,
+        LoadI { .. } => 12,
+        JumpOffset { .. } => 22,
+        Random { .. } => 36,
+        //Unaligned rows straddle two bytes of display memory.
+        Draw { n, .. } => {
+            let row_cycles = if vx.is_multiple_of(8) { 28 } else { 40 };
+            22 + n as u32 * row_cycles
+        }
+        GetDelay { .. } | WaitKey { .. } | SetDelay { .. } | SetSound { .. } => 10,
+        AddI { .. } | LoadFont { .. } => 16,
+        //Digits are found by repeated subtraction.
+        Bcd { .. } => {
+            let digits = vx / 100 + vx / 10 % 10 + vx % 10;
+            80 + 16 * digits as u32
+        }
+        Store { x } | Load { x } => 14 + 14 * (x as u32 + 1),
+        //Not VIP instructions, charged like a simple register load.
+        _ => 12,
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tickrate" => Ok(Timing::Tickrate),
+            "cosmac-vip" | "vip" => Ok(Timing::CosmacVip),
+            _ => Err(format!("Unknown timing {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Timing::Tickrate => write!(f, "tickrate"),
+            Timing::CosmacVip => write!(f, "cosmac-vip"),
+        }
+    }
+}
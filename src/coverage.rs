@@ -0,0 +1,64 @@
+//lcov tracefiles from a Profiler's counts, keyed by ROM address and optionally source line.
+
+use crate::{assembler::SourceMap, profiler::Profiler, PG_START};
+use std::{collections::BTreeMap, fmt::Write};
+
+//Per byte count a test reports.
+type Count = fn(&Profiler, u16) -> u64;
+
+//Source file the source map was built from.
+pub struct Source<'a> {
+    pub path: &'a str,
+    pub map: &'a SourceMap,
+}
+
+//lcov report for a program of program_len bytes loaded at PG_START.
+pub fn lcov(
+    profiler: &Profiler,
+    rom_path: &str,
+    program_len: usize,
+    source: Option<&Source>,
+) -> String {
+    //Every byte of an executed instruction counts, read and written are data accesses.
+    let tests: [(&str, Count); 3] = [
+        ("executed", Profiler::get_byte_executions),
+        ("read", Profiler::get_reads),
+        ("written", Profiler::get_writes),
+    ];
+    let addrs = PG_START..PG_START + program_len;
+    let mut report = String::new();
+    for (test, count) in tests {
+        let by_addr: Vec<(usize, u64)> = addrs
+            .clone()
+            .map(|addr| (addr, count(profiler, addr as u16)))
+            .collect();
+        write_record(&mut report, test, rom_path, &by_addr);
+        if let Some(source) = source {
+            //A line counts as often as its busiest byte.
+            let mut by_line = BTreeMap::new();
+            for line in source.map.lines.iter() {
+                let busiest = (line.addr..line.addr.saturating_add(line.len))
+                    .map(|addr| count(profiler, addr))
+                    .max()
+                    .unwrap_or(0);
+                let hits = by_line.entry(line.line).or_insert(0);
+                *hits = busiest.max(*hits);
+            }
+            let by_line: Vec<_> = by_line.into_iter().collect();
+            write_record(&mut report, test, source.path, &by_line);
+        }
+    }
+    report
+}
+
+fn write_record(report: &mut String, test: &str, path: &str, counts: &[(usize, u64)]) {
+    writeln!(report, "TN:{test}").unwrap();
+    writeln!(report, "SF:{path}").unwrap();
+    for (line, count) in counts {
+        writeln!(report, "DA:{line},{count}").unwrap();
+    }
+    let hit = counts.iter().filter(|(_, count)| *count > 0).count();
+    writeln!(report, "LF:{}", counts.len()).unwrap();
+    writeln!(report, "LH:{hit}").unwrap();
+    writeln!(report, "end_of_record").unwrap();
+}
@@ -0,0 +1,565 @@
+//Breakpoints, watchpoints and stepping around Chip8::execute_next.
+
+use crate::{disassembler::Disassembler, AccessKind, Chip8, EmuErr, Instruction, MemAccess};
+use std::{
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+//Instructions shown either side of the PC in the state view.
+const DISASSEMBLY_CONTEXT: u16 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+impl Register {
+    pub fn read(self, chip_8: &Chip8) -> u16 {
+        match self {
+            Register::V(reg) => chip_8.get_v_regs()[reg] as u16,
+            Register::I => chip_8.get_i_reg(),
+            Register::Pc => chip_8.get_pc(),
+            Register::Sp => chip_8.get_stack().len() as u16,
+            Register::Dt => chip_8.get_delay_reg() as u16,
+            Register::St => chip_8.get_sound_reg() as u16,
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "i" => Ok(Register::I),
+            "pc" => Ok(Register::Pc),
+            "sp" => Ok(Register::Sp),
+            "dt" => Ok(Register::Dt),
+            "st" => Ok(Register::St),
+            reg => reg
+                .strip_prefix('v')
+                .filter(|index| index.len() == 1)
+                .and_then(|index| usize::from_str_radix(index, 16).ok())
+                .map(Register::V)
+                .ok_or_else(|| format!("Unknown register {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    //Operators in matching order, two character operators before their prefixes.
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+
+    fn compare(self, lhs: u16, rhs: u16) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+}
+
+//A register comparison such as v3==0x10 or i>=0x300.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn is_met(&self, chip_8: &Chip8) -> bool {
+        self.comparison
+            .compare(self.register.read(chip_8), self.value)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let register = match self.register {
+            Register::V(reg) => format!("v{reg:x}"),
+            Register::I => "i".to_string(),
+            Register::Pc => "pc".to_string(),
+            Register::Sp => "sp".to_string(),
+            Register::Dt => "dt".to_string(),
+            Register::St => "st".to_string(),
+        };
+        let (op, _) = Comparison::OPERATORS
+            .iter()
+            .find(|(_, comparison)| *comparison == self.comparison)
+            .unwrap();
+        write!(f, "{register}{op}{:#x}", self.value)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (op_pos, op, comparison) = Comparison::OPERATORS
+            .iter()
+            .find_map(|(op, comparison)| s.find(op).map(|pos| (pos, *op, *comparison)))
+            .ok_or_else(|| format!("No comparison in condition {s}"))?;
+        Ok(Self {
+            register: s[..op_pos].trim().parse()?,
+            comparison,
+            value: parse_number(s[op_pos + op.len()..].trim())?,
+        })
+    }
+}
+
+//Stops at a PC, when a condition holds or at a PC only when it holds.
+//Without a PC it stops before every instruction while the condition holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub pc: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    fn is_hit(&self, chip_8: &Chip8) -> bool {
+        let pc_hit = self.pc.is_none_or(|pc| pc == chip_8.get_pc());
+        let condition_hit = self
+            .condition
+            .is_none_or(|condition| condition.is_met(chip_8));
+        pc_hit && condition_hit
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    //Parses "<pc>", "<condition>" or "<pc>:<condition>".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((pc, condition)) = s.split_once(':') {
+            return Ok(Self {
+                pc: Some(parse_number(pc)?),
+                condition: Some(condition.parse()?),
+            });
+        }
+        if let Ok(pc) = parse_number(s) {
+            return Ok(Self {
+                pc: Some(pc),
+                condition: None,
+            });
+        }
+        Ok(Self {
+            pc: None,
+            condition: Some(s.parse()?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+//Stops after an instruction reads or writes memory in start..=end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn is_hit(&self, access: &MemAccess) -> bool {
+        self.kind.matches(access.kind) && access.overlaps(self.start, self.end)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "r",
+            WatchKind::Write => "w",
+            WatchKind::Access => "rw",
+        };
+        write!(f, "{kind}:{:#06x}", self.start)?;
+        if self.end != self.start {
+            write!(f, "-{:#06x}", self.end)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    //Parses "<r|w|rw>:<addr>" or "<r|w|rw>:<start>-<end>", end inclusive.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, range) = s
+            .split_once(':')
+            .ok_or_else(|| format!("No access kind in watchpoint {s}"))?;
+        let kind = match kind.trim().to_ascii_lowercase().as_str() {
+            "r" => WatchKind::Read,
+            "w" => WatchKind::Write,
+            "rw" => WatchKind::Access,
+            _ => return Err(format!("Unknown access kind {kind}")),
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_number(start)?, parse_number(end)?),
+            None => {
+                let addr = parse_number(range)?;
+                (addr, addr)
+            }
+        };
+        if end < start {
+            return Err(format!("Empty watchpoint range {range}"));
+        }
+        Ok(Self { start, end, kind })
+    }
+}
+
+#[derive(Debug)]
+pub enum StopReason {
+    Paused,
+    Breakpoint(Breakpoint),
+    Watchpoint {
+        watchpoint: Watchpoint,
+        access: MemAccess,
+        pc: u16,
+        instruction: Instruction,
+    },
+    Step,
+    Error(EmuErr),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Paused => write!(f, "Paused"),
+            StopReason::Breakpoint(Breakpoint { pc, condition }) => {
+                write!(f, "Breakpoint")?;
+                if let Some(pc) = pc {
+                    write!(f, " at {pc:#06x}")?;
+                }
+                if let Some(condition) = condition {
+                    write!(f, " when {condition}")?;
+                }
+                Ok(())
+            }
+            StopReason::Watchpoint {
+                watchpoint,
+                access,
+                pc,
+                instruction,
+            } => {
+                let action = match access.kind {
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                };
+                write!(
+                    f,
+                    "Watchpoint {watchpoint} hit by {action} of {} bytes at {:#06x} from {instruction} at {pc:#06x}",
+                    access.len, access.addr
+                )
+            }
+            StopReason::Step => write!(f, "Step"),
+            StopReason::Error(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+//Stepping that spans several instructions, finished once the stack is back at depth.
+#[derive(Debug, Clone, Copy)]
+enum StepMode {
+    Over { return_pc: u16, depth: usize },
+    Out { depth: usize },
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    paused: bool,
+    step_mode: Option<StepMode>,
+    //Lets execution leave the PC it stopped at without hitting the same breakpoint.
+    skip_breakpoints: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            paused: false,
+            step_mode: None,
+            skip_breakpoints: false,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) {
+        self.breakpoints.retain(|existing| existing != breakpoint);
+    }
+
+    //Adds an unconditional breakpoint at pc, or removes it if it was already set.
+    pub fn toggle_breakpoint(&mut self, pc: u16) -> bool {
+        let breakpoint = Breakpoint {
+            pc: Some(pc),
+            condition: None,
+        };
+        if self.breakpoints.contains(&breakpoint) {
+            self.remove_breakpoint(&breakpoint);
+            false
+        } else {
+            self.add_breakpoint(breakpoint);
+            true
+        }
+    }
+
+    pub fn get_breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) {
+        self.watchpoints.retain(|existing| existing != watchpoint);
+    }
+
+    pub fn get_watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.step_mode = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.step_mode = None;
+        self.skip_breakpoints = true;
+    }
+
+    //Executes a single instruction and stays paused.
+    pub fn step(&mut self, chip_8: &mut Chip8) -> StopReason {
+        self.paused = true;
+        self.step_mode = None;
+        //Frames aren't started while paused, stepping past the end of one starts the next.
+        if chip_8.is_frame_done() && !chip_8.is_halted() {
+            chip_8.start_frame();
+        }
+        self.execute(chip_8).unwrap_or(StopReason::Step)
+    }
+
+    //Runs a CALL through to its return, anything else is a single step.
+    pub fn step_over(&mut self, chip_8: &mut Chip8) -> Option<StopReason> {
+        let pc = chip_8.get_pc();
+        let is_call = chip_8
+            .get_memory()
+            .get(pc as usize)
+            .is_some_and(|high_byte| high_byte >> 4 == 0x2);
+        if !is_call {
+            return Some(self.step(chip_8));
+        }
+        self.resume();
+        self.step_mode = Some(StepMode::Over {
+            return_pc: pc.wrapping_add(2),
+            depth: chip_8.get_stack().len(),
+        });
+        None
+    }
+
+    //Runs until the current subroutine returns, outside of one there's nothing to step out of.
+    pub fn step_out(&mut self, chip_8: &Chip8) {
+        let Some(depth) = chip_8.get_stack().len().checked_sub(1) else {
+            return;
+        };
+        self.resume();
+        self.step_mode = Some(StepMode::Out { depth });
+    }
+
+    //Executes up to max_instructions unless paused, returning why execution stopped early.
+    pub fn run(&mut self, chip_8: &mut Chip8, max_instructions: u32) -> Option<StopReason> {
+        for _ in 0..max_instructions {
+            if self.paused {
+                return None;
+            }
+            if let Some(reason) = self.check_stop(chip_8) {
+                self.pause();
+                return Some(reason);
+            }
+            self.skip_breakpoints = false;
+            if let Some(reason) = self.execute(chip_8) {
+                self.pause();
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    //Runs a 60Hz frame like Chip8::run_frame, stopping at breakpoints and watchpoints.
+    pub fn run_frame(&mut self, chip_8: &mut Chip8) -> Option<StopReason> {
+        if self.paused {
+            return None;
+        }
+        chip_8.start_frame();
+        while !chip_8.is_frame_done() {
+            if let Some(reason) = self.run(chip_8, 1) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    //Executes one instruction, stopping on errors and watched memory accesses.
+    fn execute(&self, chip_8: &mut Chip8) -> Option<StopReason> {
+        let pc = chip_8.get_pc();
+        let memory = chip_8.get_memory();
+        let opcode = match (memory.get(pc as usize), memory.get(pc as usize + 1)) {
+            (Some(high_byte), Some(low_byte)) => u16::from_be_bytes([*high_byte, *low_byte]),
+            _ => 0,
+        };
+        if let Err(err) = chip_8.execute_next() {
+            return Some(StopReason::Error(err));
+        }
+        chip_8.get_mem_accesses().iter().find_map(|access| {
+            let watchpoint = self
+                .watchpoints
+                .iter()
+                .find(|watchpoint| watchpoint.is_hit(access))?;
+            Some(StopReason::Watchpoint {
+                watchpoint: *watchpoint,
+                access: *access,
+                pc,
+                instruction: Instruction::new(opcode),
+            })
+        })
+    }
+
+    fn check_stop(&self, chip_8: &Chip8) -> Option<StopReason> {
+        let depth = chip_8.get_stack().len();
+        let step_done = match self.step_mode {
+            Some(StepMode::Over {
+                return_pc,
+                depth: call_depth,
+            }) => chip_8.get_pc() == return_pc && depth == call_depth,
+            Some(StepMode::Out {
+                depth: return_depth,
+            }) => depth == return_depth,
+            None => false,
+        };
+        if step_done {
+            return Some(StopReason::Step);
+        }
+        if self.skip_breakpoints {
+            return None;
+        }
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| breakpoint.is_hit(chip_8))
+            .map(|breakpoint| StopReason::Breakpoint(*breakpoint))
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Registers, timers, stack and the disassembled instructions around the PC.
+pub fn state_view(chip_8: &Chip8) -> String {
+    let mut view = String::new();
+    for (reg, val) in chip_8.get_v_regs().iter().enumerate() {
+        let separator = if reg % 8 == 7 { '\n' } else { ' ' };
+        write!(view, "V{reg:X}={val:02x}{separator}").unwrap();
+    }
+    writeln!(
+        view,
+        "I={:04x} PC={:04x} DT={:02x} ST={:02x}",
+        chip_8.get_i_reg(),
+        chip_8.get_pc(),
+        chip_8.get_delay_reg(),
+        chip_8.get_sound_reg()
+    )
+    .unwrap();
+    write!(view, "Stack:").unwrap();
+    for entry in chip_8.get_stack() {
+        write!(view, " {entry:04x}").unwrap();
+    }
+    writeln!(view).unwrap();
+
+    let memory = chip_8.get_memory();
+    let disassembler = Disassembler::new(chip_8.get_platform());
+    let pc = chip_8.get_pc();
+    let start = pc.saturating_sub(DISASSEMBLY_CONTEXT * 2);
+    for addr in (start..=pc.saturating_add(DISASSEMBLY_CONTEXT * 2)).step_by(2) {
+        let (Some(high_byte), Some(low_byte)) =
+            (memory.get(addr as usize), memory.get(addr as usize + 1))
+        else {
+            break;
+        };
+        let marker = if addr == pc { '>' } else { ' ' };
+        let mnemonic = disassembler
+            .instruction(&memory[addr as usize..])
+            .map_or(String::new(), |(mnemonic, _)| mnemonic);
+        let line = format!("{marker} {addr:04x}: {high_byte:02x}{low_byte:02x}  {mnemonic}");
+        writeln!(view, "{}", line.trim_end()).unwrap();
+    }
+    view
+}
+
+//Accepts decimal or 0x prefixed hex.
+fn parse_number(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number {s}"))
+}
@@ -0,0 +1,475 @@
+//Octo or Cowgod style listings, only bytes reachable from PG_START are decoded as code.
+
+use crate::{Opcode, Platform, PG_START};
+use std::{collections::BTreeMap, fmt::Write, str::FromStr};
+
+//Data bytes listed per line.
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    //Octo assembly, `v3 := 0x1f`.
+    #[default]
+    Octo,
+    //Cowgod's technical reference, `ld v3, 0x1f`.
+    Cowgod,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "octo" => Ok(Syntax::Octo),
+            "cowgod" => Ok(Syntax::Cowgod),
+            _ => Err(format!("Unknown syntax {s}")),
+        }
+    }
+}
+
+//Where execution can go after an instruction.
+#[derive(Debug, Clone, Copy)]
+enum Flow {
+    Next,
+    //Conditional skip, either the next instruction or the one after it.
+    Skip,
+    Jump(u16),
+    Call(u16),
+    //BNNN, the offset register isn't known so only the table base is followed.
+    JumpTable(u16),
+    Stop,
+}
+
+//Kinds of label, in order of precedence when an address has several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Jump,
+    Call,
+    Entry,
+}
+
+struct Decoded {
+    len: usize,
+    flow: Flow,
+    //Address loaded into I, labelled as data.
+    pointer: Option<u16>,
+    text: String,
+}
+
+//A disassembled instruction, or a run of bytes that were never reached.
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub label: Option<String>,
+    //None for data.
+    pub text: Option<String>,
+}
+
+pub struct Disassembler {
+    platform: Platform,
+    syntax: Syntax,
+}
+
+impl Disassembler {
+    pub fn new(platform: Platform) -> Self {
+        Self {
+            platform,
+            syntax: Syntax::default(),
+        }
+    }
+
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    //Mnemonic and length of the instruction at the start of bytes, None if it isn't one.
+    pub fn instruction(&self, bytes: &[u8]) -> Option<(String, usize)> {
+        let decoded = self.decode(bytes, &|addr| format!("{addr:#05x}"))?;
+        Some((decoded.text, decoded.len))
+    }
+
+    //Disassembles a program loaded at PG_START.
+    pub fn disassemble(&self, program: &[u8]) -> Vec<Line> {
+        let (is_code, labels) = self.trace(program);
+        let name = |addr: u16| {
+            labels
+                .get(&addr)
+                .cloned()
+                .unwrap_or_else(|| format!("{addr:#05x}"))
+        };
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < program.len() {
+            let addr = (PG_START + offset) as u16;
+            let label = labels.get(&addr).cloned();
+            if is_code[offset] {
+                let decoded = self
+                    .decode(&program[offset..], &name)
+                    .expect("Traced instructions decode");
+                lines.push(Line {
+                    addr,
+                    bytes: program[offset..offset + decoded.len].to_vec(),
+                    label,
+                    text: Some(decoded.text),
+                });
+                offset += decoded.len;
+                continue;
+            }
+            //Data runs end at code, labels, or the line length.
+            let mut end = offset + 1;
+            while end < program.len()
+                && end - offset < DATA_PER_LINE
+                && !is_code[end]
+                && !labels.contains_key(&((PG_START + end) as u16))
+            {
+                end += 1;
+            }
+            lines.push(Line {
+                addr,
+                bytes: program[offset..end].to_vec(),
+                label,
+                text: None,
+            });
+            offset = end;
+        }
+        lines
+    }
+
+    //Full listing, an Octo source file or a Cowgod listing with addresses and opcodes.
+    pub fn listing(&self, program: &[u8]) -> String {
+        let mut listing = String::new();
+        for line in self.disassemble(program) {
+            match self.syntax {
+                Syntax::Octo => {
+                    if let Some(label) = &line.label {
+                        writeln!(listing, ": {label}").unwrap();
+                    }
+                    let text = line.text.unwrap_or_else(|| {
+                        let bytes: Vec<_> = line
+                            .bytes
+                            .iter()
+                            .map(|byte| format!("{byte:#04x}"))
+                            .collect();
+                        bytes.join(" ")
+                    });
+                    writeln!(listing, "\t{text}").unwrap();
+                }
+                Syntax::Cowgod => {
+                    if let Some(label) = &line.label {
+                        writeln!(listing, "{label}:").unwrap();
+                    }
+                    let hex: String = line
+                        .bytes
+                        .iter()
+                        .map(|byte| format!("{byte:02x}"))
+                        .collect();
+                    let text = line.text.unwrap_or_else(|| {
+                        let bytes: Vec<_> = line
+                            .bytes
+                            .iter()
+                            .map(|byte| format!("{byte:#04x}"))
+                            .collect();
+                        format!("db {}", bytes.join(", "))
+                    });
+                    writeln!(listing, "{:04x}: {hex:<16} {text}", line.addr).unwrap();
+                }
+            }
+        }
+        listing
+    }
+
+    //Follows control flow from PG_START, marking the first byte of every reachable
+    //instruction and naming the targets that land on a line.
+    fn trace(&self, program: &[u8]) -> (Vec<bool>, BTreeMap<u16, String>) {
+        let mut is_code = vec![false; program.len()];
+        let mut covered = vec![false; program.len()];
+        let mut targets = BTreeMap::new();
+        let add_target = |targets: &mut BTreeMap<u16, LabelKind>, addr: u16, kind| {
+            let existing = targets.entry(addr).or_insert(kind);
+            *existing = (*existing).max(kind);
+        };
+        add_target(&mut targets, PG_START as u16, LabelKind::Entry);
+
+        let mut pending = vec![PG_START as u16];
+        while let Some(addr) = pending.pop() {
+            let Some(offset) = (addr as usize).checked_sub(PG_START) else {
+                continue;
+            };
+            if offset >= program.len() || covered[offset] {
+                continue;
+            }
+            let Some(decoded) = self.decode(&program[offset..], &|_| String::new()) else {
+                continue;
+            };
+            //Overlaps an instruction reached another way, leave it to the first.
+            if covered[offset..offset + decoded.len].contains(&true) {
+                continue;
+            }
+            is_code[offset] = true;
+            covered[offset..offset + decoded.len].fill(true);
+
+            let next = addr.wrapping_add(decoded.len as u16);
+            if let Some(pointer) = decoded.pointer {
+                add_target(&mut targets, pointer, LabelKind::Data);
+            }
+            match decoded.flow {
+                Flow::Next => pending.push(next),
+                Flow::Skip => {
+                    let next_offset = offset + decoded.len;
+                    let next_len = program
+                        .get(next_offset..)
+                        .and_then(|bytes| self.decode(bytes, &|_| String::new()))
+                        .map_or(2, |decoded| decoded.len);
+                    pending.push(next.wrapping_add(next_len as u16));
+                    pending.push(next);
+                }
+                Flow::Jump(target) => {
+                    add_target(&mut targets, target, LabelKind::Jump);
+                    pending.push(target);
+                }
+                Flow::Call(target) => {
+                    add_target(&mut targets, target, LabelKind::Call);
+                    pending.push(next);
+                    pending.push(target);
+                }
+                Flow::JumpTable(target) => {
+                    add_target(&mut targets, target, LabelKind::Jump);
+                    pending.push(target);
+                }
+                Flow::Stop => {}
+            }
+        }
+
+        //Targets in the middle of an instruction can't be labelled.
+        let labels = targets
+            .into_iter()
+            .filter(|(addr, _)| {
+                let offset = (*addr as usize).wrapping_sub(PG_START);
+                offset < program.len() && (is_code[offset] || !covered[offset])
+            })
+            .map(|(addr, kind)| {
+                let name = match kind {
+                    LabelKind::Entry => "main".to_string(),
+                    LabelKind::Call => format!("sub_{addr:x}"),
+                    LabelKind::Jump => format!("loc_{addr:x}"),
+                    LabelKind::Data => format!("data_{addr:x}"),
+                };
+                (addr, name)
+            })
+            .collect();
+        (is_code, labels)
+    }
+
+    fn decode(&self, bytes: &[u8], name: &dyn Fn(u16) -> String) -> Option<Decoded> {
+        use Opcode::*;
+        let word = u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]);
+        let opcode = Opcode::decode(word, self.platform).ok()?;
+        let mut pointer = None;
+        let (flow, octo, cowgod) = match opcode {
+            ScrollDown { n } => (Flow::Next, format!("scroll-down {n}"), format!("scd {n}")),
+            ScrollUp { n } => (Flow::Next, format!("scroll-up {n}"), format!("scu {n}")),
+            Clear => (Flow::Next, "clear".to_string(), "cls".to_string()),
+            Return => (Flow::Stop, "return".to_string(), "ret".to_string()),
+            ScrollRight => (Flow::Next, "scroll-right".to_string(), "scr".to_string()),
+            ScrollLeft => (Flow::Next, "scroll-left".to_string(), "scl".to_string()),
+            Exit => (Flow::Stop, "exit".to_string(), "exit".to_string()),
+            LowRes => (Flow::Next, "lores".to_string(), "low".to_string()),
+            HighRes => (Flow::Next, "hires".to_string(), "high".to_string()),
+            Jump { nnn } => (
+                Flow::Jump(nnn),
+                format!("jump {}", name(nnn)),
+                format!("jp {}", name(nnn)),
+            ),
+            Call { nnn } => {
+                let target = name(nnn);
+                //Octo calls a subroutine by naming its label.
+                let octo = if target.starts_with("0x") {
+                    format!(":call {target}")
+                } else {
+                    target.clone()
+                };
+                (Flow::Call(nnn), octo, format!("call {target}"))
+            }
+            SkipEqImm { x, kk } => (
+                Flow::Skip,
+                format!("if v{x:x} != {kk:#04x} then"),
+                format!("se v{x:x}, {kk:#04x}"),
+            ),
+            SkipNeImm { x, kk } => (
+                Flow::Skip,
+                format!("if v{x:x} == {kk:#04x} then"),
+                format!("sne v{x:x}, {kk:#04x}"),
+            ),
+            SkipEqReg { x, y } => (
+                Flow::Skip,
+                format!("if v{x:x} != v{y:x} then"),
+                format!("se v{x:x}, v{y:x}"),
+            ),
+            SaveRange { x, y } => (
+                Flow::Next,
+                format!("save v{x:x} - v{y:x}"),
+                format!("save v{x:x}, v{y:x}"),
+            ),
+            LoadRange { x, y } => (
+                Flow::Next,
+                format!("load v{x:x} - v{y:x}"),
+                format!("load v{x:x}, v{y:x}"),
+            ),
+            LoadImm { x, kk } => (
+                Flow::Next,
+                format!("v{x:x} := {kk:#04x}"),
+                format!("ld v{x:x}, {kk:#04x}"),
+            ),
+            AddImm { x, kk } => (
+                Flow::Next,
+                format!("v{x:x} += {kk:#04x}"),
+                format!("add v{x:x}, {kk:#04x}"),
+            ),
+            Move { x, y }
+            | Or { x, y }
+            | And { x, y }
+            | Xor { x, y }
+            | AddReg { x, y }
+            | Sub { x, y }
+            | ShiftRight { x, y }
+            | SubReverse { x, y }
+            | ShiftLeft { x, y } => {
+                let (octo_op, cowgod_op) = match opcode {
+                    Move { .. } => (":=", "ld"),
+                    Or { .. } => ("|=", "or"),
+                    And { .. } => ("&=", "and"),
+                    Xor { .. } => ("^=", "xor"),
+                    AddReg { .. } => ("+=", "add"),
+                    Sub { .. } => ("-=", "sub"),
+                    ShiftRight { .. } => (">>=", "shr"),
+                    SubReverse { .. } => ("=-", "subn"),
+                    _ => ("<<=", "shl"),
+                };
+                (
+                    Flow::Next,
+                    format!("v{x:x} {octo_op} v{y:x}"),
+                    format!("{cowgod_op} v{x:x}, v{y:x}"),
+                )
+            }
+            SkipNeReg { x, y } => (
+                Flow::Skip,
+                format!("if v{x:x} == v{y:x} then"),
+                format!("sne v{x:x}, v{y:x}"),
+            ),
+            LoadI { nnn } => {
+                pointer = Some(nnn);
+                (
+                    Flow::Next,
+                    format!("i := {}", name(nnn)),
+                    format!("ld i, {}", name(nnn)),
+                )
+            }
+            JumpOffset { nnn } => (
+                Flow::JumpTable(nnn),
+                format!("jump0 {}", name(nnn)),
+                format!("jp v0, {}", name(nnn)),
+            ),
+            Random { x, kk } => (
+                Flow::Next,
+                format!("v{x:x} := random {kk:#04x}"),
+                format!("rnd v{x:x}, {kk:#04x}"),
+            ),
+            Draw { x, y, n } => (
+                Flow::Next,
+                format!("sprite v{x:x} v{y:x} {n}"),
+                format!("drw v{x:x}, v{y:x}, {n}"),
+            ),
+            SkipKey { x } => (
+                Flow::Skip,
+                format!("if v{x:x} -key then"),
+                format!("skp v{x:x}"),
+            ),
+            SkipNotKey { x } => (
+                Flow::Skip,
+                format!("if v{x:x} key then"),
+                format!("sknp v{x:x}"),
+            ),
+            LoadILong => {
+                let long = u16::from_be_bytes([*bytes.get(2)?, *bytes.get(3)?]);
+                pointer = Some(long);
+                (
+                    Flow::Next,
+                    format!("i := long {}", name(long)),
+                    format!("ld i, long {}", name(long)),
+                )
+            }
+            Plane { n } => (Flow::Next, format!("plane {n}"), format!("plane {n}")),
+            Audio => (Flow::Next, "audio".to_string(), "audio".to_string()),
+            GetDelay { x } => (
+                Flow::Next,
+                format!("v{x:x} := delay"),
+                format!("ld v{x:x}, dt"),
+            ),
+            WaitKey { x } => (
+                Flow::Next,
+                format!("v{x:x} := key"),
+                format!("ld v{x:x}, k"),
+            ),
+            SetDelay { x } => (
+                Flow::Next,
+                format!("delay := v{x:x}"),
+                format!("ld dt, v{x:x}"),
+            ),
+            SetSound { x } => (
+                Flow::Next,
+                format!("buzzer := v{x:x}"),
+                format!("ld st, v{x:x}"),
+            ),
+            AddI { x } => (Flow::Next, format!("i += v{x:x}"), format!("add i, v{x:x}")),
+            LoadFont { x } => (
+                Flow::Next,
+                format!("i := hex v{x:x}"),
+                format!("ld f, v{x:x}"),
+            ),
+            LoadBigFont { x } => (
+                Flow::Next,
+                format!("i := bighex v{x:x}"),
+                format!("ld hf, v{x:x}"),
+            ),
+            Bcd { x } => (Flow::Next, format!("bcd v{x:x}"), format!("ld b, v{x:x}")),
+            Pitch { x } => (
+                Flow::Next,
+                format!("pitch := v{x:x}"),
+                format!("pitch v{x:x}"),
+            ),
+            Store { x } => (
+                Flow::Next,
+                format!("save v{x:x}"),
+                format!("ld [i], v{x:x}"),
+            ),
+            Load { x } => (
+                Flow::Next,
+                format!("load v{x:x}"),
+                format!("ld v{x:x}, [i]"),
+            ),
+            SaveFlags { x } => (
+                Flow::Next,
+                format!("saveflags v{x:x}"),
+                format!("ld r, v{x:x}"),
+            ),
+            LoadFlags { x } => (
+                Flow::Next,
+                format!("loadflags v{x:x}"),
+                format!("ld v{x:x}, r"),
+            ),
+        };
+        let text = match self.syntax {
+            Syntax::Octo => octo,
+            Syntax::Cowgod => cowgod,
+        };
+        Some(Decoded {
+            len: opcode.size(),
+            flow,
+            pointer,
+            text,
+        })
+    }
+}
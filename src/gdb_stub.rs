@@ -0,0 +1,504 @@
+//GDB remote serial protocol stub, polled once per frame so it never blocks the emulator.
+
+use crate::{
+    debugger::{Breakpoint, Debugger, Register, StopReason, WatchKind, Watchpoint},
+    AccessKind, Chip8, EmuErr,
+};
+use std::{
+    fmt::Write as _,
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+};
+
+//Largest packet the client may send, advertised through qSupported.
+const PACKET_SIZE: usize = 0x1000;
+const INTERRUPT: u8 = 0x03;
+//Signals reported in stop replies.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+const REGISTERS: [Register; 21] = [
+    Register::V(0x0),
+    Register::V(0x1),
+    Register::V(0x2),
+    Register::V(0x3),
+    Register::V(0x4),
+    Register::V(0x5),
+    Register::V(0x6),
+    Register::V(0x7),
+    Register::V(0x8),
+    Register::V(0x9),
+    Register::V(0xA),
+    Register::V(0xB),
+    Register::V(0xC),
+    Register::V(0xD),
+    Register::V(0xE),
+    Register::V(0xF),
+    Register::I,
+    Register::Pc,
+    Register::Sp,
+    Register::Dt,
+    Register::St,
+];
+
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    //Bytes received that don't yet make up a whole packet.
+    buffer: Vec<u8>,
+    no_ack: bool,
+    //Set while the client waits for a stop reply to continue.
+    running: bool,
+    //Breakpoints and watchpoints set by the client, removed again when it detaches.
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl GdbStub {
+    //Listens on localhost only, the protocol has no authentication.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: None,
+            buffer: Vec::new(),
+            no_ack: false,
+            running: false,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.client.is_some()
+    }
+
+    //Accepts a waiting client and handles every packet it has sent.
+    pub fn poll(&mut self, chip_8: &mut Chip8, debugger: &mut Debugger) -> io::Result<()> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, _)) => self.attach(stream, debugger)?,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+        let Some(client) = self.client.as_mut() else {
+            return Ok(());
+        };
+        let mut chunk = [0u8; PACKET_SIZE];
+        loop {
+            match client.read(&mut chunk) {
+                Ok(0) => {
+                    self.detach(debugger);
+                    return Ok(());
+                }
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    self.detach(debugger);
+                    return Err(err);
+                }
+            }
+        }
+        while let Some(packet) = self.next_packet()? {
+            let Some(packet) = packet else {
+                //Ctrl-C from the client while the target is running.
+                if self.running {
+                    debugger.pause();
+                    self.running = false;
+                    self.send(&format!("S{SIGINT:02x}"))?;
+                }
+                continue;
+            };
+            if !self.handle_packet(&packet, chip_8, debugger)? {
+                self.detach(debugger);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    //Sends the stop reply a client is waiting on after continue.
+    pub fn report_stop(&mut self, reason: &StopReason) -> io::Result<()> {
+        if !self.running {
+            return Ok(());
+        }
+        self.running = false;
+        self.send(&stop_reply(reason))
+    }
+
+    //Tells the client the program exited through 00FD.
+    pub fn report_exit(&mut self) -> io::Result<()> {
+        if self.client.is_none() {
+            return Ok(());
+        }
+        self.running = false;
+        self.send("W00")
+    }
+
+    fn attach(&mut self, stream: TcpStream, debugger: &mut Debugger) -> io::Result<()> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        self.client = Some(stream);
+        self.buffer.clear();
+        self.no_ack = false;
+        self.running = false;
+        //Clients expect the target to be stopped once attached.
+        debugger.pause();
+        Ok(())
+    }
+
+    fn detach(&mut self, debugger: &mut Debugger) {
+        for breakpoint in self.breakpoints.drain(..) {
+            debugger.remove_breakpoint(&breakpoint);
+        }
+        for watchpoint in self.watchpoints.drain(..) {
+            debugger.remove_watchpoint(&watchpoint);
+        }
+        self.client = None;
+        self.running = false;
+        debugger.resume();
+    }
+
+    //Takes the next packet out of the buffer, None inside is an interrupt.
+    fn next_packet(&mut self) -> io::Result<Option<Option<String>>> {
+        loop {
+            match self.buffer.first() {
+                None => return Ok(None),
+                Some(b'+') | Some(b'-') => {
+                    self.buffer.remove(0);
+                }
+                Some(&INTERRUPT) => {
+                    self.buffer.remove(0);
+                    return Ok(Some(None));
+                }
+                Some(b'$') => break,
+                //Garbage between packets.
+                Some(_) => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        let Some(end) = self.buffer.iter().position(|byte| *byte == b'#') else {
+            return Ok(None);
+        };
+        if self.buffer.len() < end + 3 {
+            return Ok(None);
+        }
+        let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+        let data = &packet[1..end];
+        let checksum = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+        let valid = checksum == Some(packet_checksum(data));
+        if !self.no_ack {
+            let ack: &[u8] = if valid { b"+" } else { b"-" };
+            self.write(ack)?;
+        }
+        if !valid {
+            return self.next_packet();
+        }
+        Ok(Some(Some(String::from_utf8_lossy(data).into_owned())))
+    }
+
+    //Returns false once the client has detached or killed the target.
+    fn handle_packet(
+        &mut self,
+        packet: &str,
+        chip_8: &mut Chip8,
+        debugger: &mut Debugger,
+    ) -> io::Result<bool> {
+        let Some(command) = packet.get(..1) else {
+            self.send("")?;
+            return Ok(true);
+        };
+        let args = &packet[1..];
+        let reply = match command {
+            "?" => format!("S{SIGTRAP:02x}"),
+            "g" => REGISTERS
+                .iter()
+                .map(|register| register_hex(*register, chip_8))
+                .collect(),
+            "G" => reply_ok(write_registers(args, chip_8)),
+            "p" => parse_hex(args)
+                .and_then(|index| REGISTERS.get(index as usize))
+                .map_or_else(error_reply, |register| register_hex(*register, chip_8)),
+            "P" => reply_ok(args.split_once('=').and_then(|(index, val)| {
+                let register = REGISTERS.get(parse_hex(index)? as usize)?;
+                write_register(*register, &decode_hex(val)?, chip_8)
+            })),
+            "m" => read_memory(args, chip_8).unwrap_or_else(error_reply),
+            "M" => reply_ok(write_memory(args, chip_8)),
+            "Z" | "z" => self
+                .set_breakpoint(command == "Z", args, debugger)
+                .unwrap_or_default(),
+            "c" => {
+                if let Some(pc) = parse_hex(args) {
+                    chip_8.set_pc(pc as u16);
+                }
+                debugger.resume();
+                self.running = true;
+                return Ok(true);
+            }
+            "s" => {
+                if let Some(pc) = parse_hex(args) {
+                    chip_8.set_pc(pc as u16);
+                }
+                stop_reply(&debugger.step(chip_8))
+            }
+            "H" => "OK".to_string(),
+            "k" => return Ok(false),
+            "D" => {
+                self.send("OK")?;
+                return Ok(false);
+            }
+            "q" => self.query(args),
+            "Q" if args == "StartNoAckMode" => {
+                self.send("OK")?;
+                self.no_ack = true;
+                return Ok(true);
+            }
+            //Unsupported packets get an empty reply.
+            _ => String::new(),
+        };
+        self.send(&reply)?;
+        Ok(true)
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return format!(
+                "PacketSize={PACKET_SIZE:x};qXfer:features:read+;swbreak+;QStartNoAckMode+"
+            );
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, len)) = range.split_once(',') else {
+                return error_reply();
+            };
+            let (Some(offset), Some(len)) = (parse_hex(offset), parse_hex(len)) else {
+                return error_reply();
+            };
+            let xml = target_xml();
+            let start = (offset as usize).min(xml.len());
+            let end = (start + len as usize).min(xml.len());
+            let marker = if end == xml.len() { 'l' } else { 'm' };
+            return format!("{marker}{}", &xml[start..end]);
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    //Handles Z/z packets: type 0 and 1 are breakpoints, 2 to 4 are write, read and access watchpoints.
+    fn set_breakpoint(
+        &mut self,
+        insert: bool,
+        args: &str,
+        debugger: &mut Debugger,
+    ) -> Option<String> {
+        let mut fields = args.split(',');
+        let kind = fields.next()?;
+        let addr = parse_hex(fields.next()?)? as u16;
+        let len = fields
+            .next()
+            .and_then(parse_hex)
+            .map_or(1, |len| len.max(1) as u16);
+        let watch_kind = match kind {
+            "0" | "1" => {
+                let breakpoint = Breakpoint {
+                    pc: Some(addr),
+                    condition: None,
+                };
+                if insert {
+                    debugger.add_breakpoint(breakpoint);
+                    self.breakpoints.push(breakpoint);
+                } else {
+                    debugger.remove_breakpoint(&breakpoint);
+                    self.breakpoints.retain(|existing| *existing != breakpoint);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return None,
+        };
+        let watchpoint = Watchpoint {
+            start: addr,
+            end: addr.saturating_add(len - 1),
+            kind: watch_kind,
+        };
+        if insert {
+            debugger.add_watchpoint(watchpoint);
+            self.watchpoints.push(watchpoint);
+        } else {
+            debugger.remove_watchpoint(&watchpoint);
+            self.watchpoints.retain(|existing| *existing != watchpoint);
+        }
+        Some("OK".to_string())
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", packet_checksum(data.as_bytes()));
+        self.write(packet.as_bytes())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match self.client.as_mut() {
+            Some(client) => client.write_all(bytes),
+            None => Ok(()),
+        }
+    }
+}
+
+fn stop_reply(reason: &StopReason) -> String {
+    match reason {
+        StopReason::Paused => format!("S{SIGINT:02x}"),
+        StopReason::Breakpoint(_) => format!("T{SIGTRAP:02x}swbreak:;"),
+        StopReason::Watchpoint {
+            watchpoint, access, ..
+        } => {
+            let kind = match (watchpoint.kind, access.kind) {
+                (WatchKind::Access, _) => "awatch",
+                (_, AccessKind::Read) => "rwatch",
+                (_, AccessKind::Write) => "watch",
+            };
+            let addr = access.addr.max(watchpoint.start);
+            format!("T{SIGTRAP:02x}{kind}:{addr:x};")
+        }
+        StopReason::Step => format!("S{SIGTRAP:02x}"),
+        StopReason::Error(EmuErr::BadInstruction { .. }) => format!("S{SIGILL:02x}"),
+        StopReason::Error(_) => format!("S{SIGSEGV:02x}"),
+    }
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\"?>",
+        "<!DOCTYPE target SYSTEM \"gdb-target.dtd\">",
+        "<target version=\"1.0\">",
+        "<feature name=\"org.chip8.core\">",
+    ));
+    for (regnum, register) in REGISTERS.iter().enumerate() {
+        let (name, bitsize, kind) = match register {
+            Register::V(reg) => (format!("v{reg:x}"), 8, "uint8"),
+            Register::I => ("i".to_string(), 16, "data_ptr"),
+            Register::Pc => ("pc".to_string(), 16, "code_ptr"),
+            Register::Sp => ("sp".to_string(), 8, "uint8"),
+            Register::Dt => ("dt".to_string(), 8, "uint8"),
+            Register::St => ("st".to_string(), 8, "uint8"),
+        };
+        write!(
+            xml,
+            "<reg name=\"{name}\" bitsize=\"{bitsize}\" type=\"{kind}\" regnum=\"{regnum}\"/>"
+        )
+        .unwrap();
+    }
+    xml.push_str("</feature></target>");
+    xml
+}
+
+fn register_size(register: Register) -> usize {
+    match register {
+        Register::I | Register::Pc => 2,
+        _ => 1,
+    }
+}
+
+fn register_hex(register: Register, chip_8: &Chip8) -> String {
+    let bytes = register.read(chip_8).to_le_bytes();
+    encode_hex(&bytes[..register_size(register)])
+}
+
+//SP is the stack depth, which can only be changed by the program.
+fn write_register(register: Register, bytes: &[u8], chip_8: &mut Chip8) -> Option<()> {
+    let mut le_bytes = [0u8; 2];
+    le_bytes[..bytes.len().min(2)].copy_from_slice(&bytes[..bytes.len().min(2)]);
+    let val = u16::from_le_bytes(le_bytes);
+    match register {
+        Register::V(reg) => chip_8.set_v_reg(reg, val as u8),
+        Register::I => chip_8.set_i_reg(val),
+        Register::Pc => chip_8.set_pc(val),
+        Register::Sp if val == register.read(chip_8) => {}
+        Register::Sp => return None,
+        Register::Dt => chip_8.set_delay_reg(val as u8),
+        Register::St => chip_8.set_sound_reg(val as u8),
+    }
+    Some(())
+}
+
+fn write_registers(hex: &str, chip_8: &mut Chip8) -> Option<()> {
+    let bytes = decode_hex(hex)?;
+    let mut pos = 0;
+    for register in REGISTERS {
+        let size = register_size(register);
+        write_register(register, bytes.get(pos..pos + size)?, chip_8)?;
+        pos += size;
+    }
+    Some(())
+}
+
+//Parses "addr,len".
+fn memory_range(args: &str, memory_len: usize) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    let (addr, len) = (parse_hex(addr)? as usize, parse_hex(len)? as usize);
+    (addr + len <= memory_len).then_some((addr, len))
+}
+
+fn read_memory(args: &str, chip_8: &Chip8) -> Option<String> {
+    let memory = chip_8.get_memory();
+    let (addr, len) = memory_range(args, memory.len())?;
+    Some(encode_hex(&memory[addr..addr + len]))
+}
+
+fn write_memory(args: &str, chip_8: &mut Chip8) -> Option<()> {
+    let (range, data) = args.split_once(':')?;
+    let memory = chip_8.get_memory_mut();
+    let (addr, len) = memory_range(range, memory.len())?;
+    let bytes = decode_hex(data)?;
+    if bytes.len() != len {
+        return None;
+    }
+    memory[addr..addr + len].copy_from_slice(&bytes);
+    Some(())
+}
+
+fn reply_ok(result: Option<()>) -> String {
+    result.map_or_else(error_reply, |_| "OK".to_string())
+}
+
+fn error_reply() -> String {
+    "E01".to_string()
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn parse_hex(hex: &str) -> Option<u32> {
+    u32::from_str_radix(hex, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,19 @@
+pub mod assembler;
+mod chip_8;
+pub mod coverage;
+pub mod debugger;
+pub mod disassembler;
+pub mod gdb_stub;
+pub mod lockstep;
+pub mod movie;
+pub mod profiler;
+pub mod rewind;
+pub mod rom_db;
+pub mod scheduler;
+pub mod screenshot;
+pub mod trace;
+pub use chip_8::{
+    AccessKind, AudioPattern, Chip8, Chip8Builder, EmuErr, Instruction, MemAccess, Opcode,
+    OpcodeClass, Platform, Quirks, Rng, Timing, XorShift, AUDIO_PATTERN_LEN, DEFAULT_TICKRATE,
+    DISPLAY_HEIGHT, DISPLAY_WIDTH, PG_START,
+};
@@ -0,0 +1,308 @@
+//Runs one program on two differently configured machines in lockstep to find where they diverge.
+
+use crate::{AccessKind, Chip8, EmuErr, Opcode, OpcodeClass, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use std::fmt;
+
+//What an instruction stepped on one side, None if the machine was waiting or had exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub pc: u16,
+    pub opcode: u16,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    Pc {
+        left: u16,
+        right: u16,
+    },
+    Register {
+        reg: usize,
+        left: u8,
+        right: u8,
+    },
+    IReg {
+        left: u16,
+        right: u16,
+    },
+    Dt {
+        left: u8,
+        right: u8,
+    },
+    St {
+        left: u8,
+        right: u8,
+    },
+    Stack {
+        left: Vec<u16>,
+        right: Vec<u16>,
+    },
+    Memory {
+        addr: u16,
+        left: u8,
+        right: u8,
+    },
+    Pixel {
+        x: usize,
+        y: usize,
+        left: usize,
+        right: usize,
+    },
+    Halted {
+        left: bool,
+        right: bool,
+    },
+    //An instruction failed on one side, or both failed differently.
+    Error {
+        left: Option<String>,
+        right: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub frame: u32,
+    //Instructions stepped in lockstep before the one that diverged.
+    pub step: u64,
+    pub left: Option<Step>,
+    pub right: Option<Step>,
+    pub difference: Difference,
+}
+
+//Why the machines stopped running in lockstep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    Diverged(Divergence),
+    //Both failed with the same error on the same step, they never disagreed.
+    Failed {
+        frame: u32,
+        step: u64,
+        left: Option<Step>,
+        right: Option<Step>,
+        error: String,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Difference::*;
+        match self {
+            Pc { left, right } => write!(f, "PC {left:#05x} vs {right:#05x}"),
+            Register { reg, left, right } => write!(f, "V{reg:X} {left:#04x} vs {right:#04x}"),
+            IReg { left, right } => write!(f, "I {left:#05x} vs {right:#05x}"),
+            Dt { left, right } => write!(f, "delay timer {left} vs {right}"),
+            St { left, right } => write!(f, "sound timer {left} vs {right}"),
+            Stack { left, right } => write!(f, "stack {left:03x?} vs {right:03x?}"),
+            Memory { addr, left, right } => {
+                write!(f, "memory at {addr:#05x} {left:#04x} vs {right:#04x}")
+            }
+            Pixel { x, y, left, right } => write!(f, "pixel ({x}, {y}) {left} vs {right}"),
+            Halted { left, right } => write!(f, "halted {left} vs {right}"),
+            Error { left, right } => write!(
+                f,
+                "error {} vs {}",
+                left.as_deref().unwrap_or("none"),
+                right.as_deref().unwrap_or("none")
+            ),
+        }
+    }
+}
+
+pub struct Lockstep {
+    left: Chip8,
+    right: Chip8,
+    frame: u32,
+    step: u64,
+}
+
+impl Lockstep {
+    pub fn new(left: Chip8, right: Chip8) -> Self {
+        Self {
+            left,
+            right,
+            frame: 0,
+            step: 0,
+        }
+    }
+
+    pub fn left(&self) -> &Chip8 {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Chip8 {
+        &self.right
+    }
+
+    //Frames run so far.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn set_key(&mut self, key: usize) {
+        self.left.set_key(key);
+        self.right.set_key(key);
+    }
+
+    pub fn unset_key(&mut self, key: usize) {
+        self.left.unset_key(key);
+        self.right.unset_key(key);
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.left.is_halted() && self.right.is_halted()
+    }
+
+    //Runs a frame on both machines, stopping at the first instruction they disagree on
+    //or both fail on.
+    pub fn run_frame(&mut self) -> Option<Stop> {
+        self.left.start_frame();
+        self.right.start_frame();
+        while !self.left.is_frame_done() || !self.right.is_frame_done() {
+            if let Some(stop) = self.step() {
+                return Some(stop);
+            }
+        }
+        self.frame += 1;
+        None
+    }
+
+    //Steps whichever machines haven't finished their frame.
+    fn step(&mut self) -> Option<Stop> {
+        let (left, left_result) = step_one(&mut self.left);
+        let (right, right_result) = step_one(&mut self.right);
+        let (frame, step) = (self.frame, self.step);
+        self.step += 1;
+        let difference = match (left_result, right_result) {
+            (Ok(()), Ok(())) => self.compare(left, right),
+            (Err(left_err), Err(right_err)) if left_err.to_string() == right_err.to_string() => {
+                return Some(Stop::Failed {
+                    frame,
+                    step,
+                    left,
+                    right,
+                    error: left_err.to_string(),
+                });
+            }
+            (left_result, right_result) => Some(Difference::Error {
+                left: left_result.err().map(|err| err.to_string()),
+                right: right_result.err().map(|err| err.to_string()),
+            }),
+        };
+        difference.map(|difference| {
+            Stop::Diverged(Divergence {
+                frame,
+                step,
+                left,
+                right,
+                difference,
+            })
+        })
+    }
+
+    fn compare(&self, left_step: Option<Step>, right_step: Option<Step>) -> Option<Difference> {
+        if left_step.is_none() && right_step.is_none() {
+            return None;
+        }
+        let (left, right) = (&self.left, &self.right);
+        if left.get_pc() != right.get_pc() {
+            return Some(Difference::Pc {
+                left: left.get_pc(),
+                right: right.get_pc(),
+            });
+        }
+        let (left_regs, right_regs) = (left.get_v_regs(), right.get_v_regs());
+        if let Some(reg) = (0..0x10).find(|reg| left_regs[*reg] != right_regs[*reg]) {
+            return Some(Difference::Register {
+                reg,
+                left: left_regs[reg],
+                right: right_regs[reg],
+            });
+        }
+        if left.get_i_reg() != right.get_i_reg() {
+            return Some(Difference::IReg {
+                left: left.get_i_reg(),
+                right: right.get_i_reg(),
+            });
+        }
+        if left.get_delay_reg() != right.get_delay_reg() {
+            return Some(Difference::Dt {
+                left: left.get_delay_reg(),
+                right: right.get_delay_reg(),
+            });
+        }
+        if left.get_sound_reg() != right.get_sound_reg() {
+            return Some(Difference::St {
+                left: left.get_sound_reg(),
+                right: right.get_sound_reg(),
+            });
+        }
+        if left.get_stack() != right.get_stack() {
+            return Some(Difference::Stack {
+                left: left.get_stack().to_vec(),
+                right: right.get_stack().to_vec(),
+            });
+        }
+        if left.is_halted() != right.is_halted() {
+            return Some(Difference::Halted {
+                left: left.is_halted(),
+                right: right.is_halted(),
+            });
+        }
+        let written = left
+            .get_mem_accesses()
+            .iter()
+            .chain(right.get_mem_accesses())
+            .filter(|access| access.kind == AccessKind::Write)
+            .flat_map(|access| access.addr..access.addr.saturating_add(access.len));
+        for addr in written {
+            let left_byte = left.get_memory().get(addr as usize).copied();
+            let right_byte = right.get_memory().get(addr as usize).copied();
+            if let (Some(left), Some(right)) = (left_byte, right_byte) {
+                if left != right {
+                    return Some(Difference::Memory { addr, left, right });
+                }
+            }
+        }
+        if drew(left, left_step) || drew(right, right_step) {
+            for y in 0..DISPLAY_HEIGHT {
+                for x in 0..DISPLAY_WIDTH {
+                    let (left, right) = (left.get_pixel(x, y), right.get_pixel(x, y));
+                    if left != right {
+                        return Some(Difference::Pixel { x, y, left, right });
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+//Executes the next instruction unless the machine's frame is over.
+fn step_one(chip_8: &mut Chip8) -> (Option<Step>, Result<(), EmuErr>) {
+    if chip_8.is_frame_done() {
+        return (None, Ok(()));
+    }
+    let step = Step {
+        pc: chip_8.get_pc(),
+        opcode: fetch(chip_8, chip_8.get_pc()),
+        cycles: chip_8.get_cycles(),
+    };
+    (Some(step), chip_8.execute_next())
+}
+
+fn fetch(chip_8: &Chip8, addr: u16) -> u16 {
+    let memory = chip_8.get_memory();
+    match (memory.get(addr as usize), memory.get(addr as usize + 1)) {
+        (Some(high), Some(low)) => u16::from_be_bytes([*high, *low]),
+        _ => 0,
+    }
+}
+
+//Whether the instruction stepped could have changed the display.
+fn drew(chip_8: &Chip8, step: Option<Step>) -> bool {
+    step.is_some_and(|step| {
+        Opcode::decode(step.opcode, chip_8.get_platform())
+            .is_ok_and(|opcode| opcode.class() == OpcodeClass::Display)
+    })
+}
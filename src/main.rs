@@ -1,40 +1,236 @@
 use sdl2::{
-    event::Event, keyboard::Keycode, pixels::Color, rect::Rect, render::WindowCanvas, Sdl,
-    VideoSubsystem,
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    event::Event,
+    keyboard::{Keycode, Mod},
+    pixels::Color,
+    rect::Rect,
+    render::WindowCanvas,
+    AudioSubsystem, Sdl, VideoSubsystem,
 };
 use std::{
-    fs::File,
+    collections::HashMap,
+    fs::{self, File},
     io::{BufReader, Read},
-    thread, time,
+    path::{Path, PathBuf},
 };
 
-mod chip_8;
-use chip_8::{Chip8, Chip8Builder, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8::{
+    debugger::{state_view, Breakpoint, Debugger, StopReason, Watchpoint},
+    gdb_stub::GdbStub,
+    movie::Movie,
+    rewind::Rewind,
+    rom_db::RomDatabase,
+    scheduler::Scheduler,
+    AudioPattern, Chip8, Chip8Builder, Platform, Timing, DISPLAY_HEIGHT, DISPLAY_WIDTH,
+};
 
 const PIXEL_SIZE: u32 = 10;
+//Frames of history kept for rewinding, 30 seconds at 60Hz.
+const REWIND_FRAMES: usize = 60 * 30;
+//Frames stepped back per frame while the rewind key is held.
+const DEFAULT_REWIND_SPEED: usize = 2;
+//Platform preset for ROMs that aren't in the database.
+const DEFAULT_PRESET: &str = "schip-modern";
+//Background, plane 1, plane 2 and both planes (XO-CHIP) colours.
+const PALETTE: [Color; 4] = [
+    Color::RGB(0, 0, 0),
+    Color::RGB(0, 255, 0),
+    Color::RGB(255, 102, 0),
+    Color::RGB(255, 255, 255),
+];
+const AUDIO_SAMPLE_RATE: i32 = 44100;
+//Frequency of the buzzer tone in Hz, XO-CHIP programs set their own with FX3A.
+const DEFAULT_BUZZER_PITCH: f32 = 440.0;
+//Amplitude of the buzzer square wave, 0.0 (silent) to 1.0 (full scale).
+const DEFAULT_BUZZER_VOLUME: f32 = 0.1;
+
+struct Buzzer {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+    sample_rate: u32,
+    active: bool,
+    //XO-CHIP programs play their own audio pattern instead of the square wave.
+    audio_pattern: Option<AudioPattern>,
+}
+
+impl Buzzer {
+    fn new(pitch: f32, volume: f32, sample_rate: i32) -> Self {
+        Self {
+            phase_inc: pitch / sample_rate as f32,
+            phase: 0.0,
+            volume,
+            sample_rate: sample_rate as u32,
+            active: false,
+            audio_pattern: None,
+        }
+    }
+
+    //Copies the pattern and pitch, keeping the playback position so the tone doesn't click.
+    fn sync_audio_pattern(&mut self, chip_8: &Chip8) {
+        let source = chip_8.get_audio_pattern();
+        let audio_pattern = self.audio_pattern.get_or_insert_with(AudioPattern::new);
+        audio_pattern.set_pattern(source.pattern());
+        audio_pattern.set_pitch(source.pitch());
+    }
+}
+
+impl AudioCallback for Buzzer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        if !self.active {
+            out.fill(0.0);
+            return;
+        }
+        if let Some(audio_pattern) = &mut self.audio_pattern {
+            audio_pattern.fill_audio(out, self.sample_rate);
+            for sample in out.iter_mut() {
+                *sample *= self.volume;
+            }
+            return;
+        }
+        for sample in out.iter_mut() {
+            *sample = if self.phase < 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+struct Options {
+    rom: String,
+    preset: String,
+    db_dir: Option<PathBuf>,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    write_protection: bool,
+    gdb_port: Option<u16>,
+    seed: Option<u64>,
+    record_movie: Option<PathBuf>,
+    play_movie: Option<PathBuf>,
+    vsync: bool,
+    timing: Option<Timing>,
+    tickrate: Option<u32>,
+    max_catch_up: Option<u32>,
+    buzzer_pitch: f32,
+    buzzer_volume: f32,
+    rewind_speed: usize,
+}
 
 fn main() {
+    let options = parse_args();
+    let rom_path = format!("rom/{}", options.rom);
+    let program = load_program_bytes(&rom_path);
+    let rom_db = match &options.db_dir {
+        Some(dir) => RomDatabase::from_dir(dir).expect("Could not load ROM database"),
+        None => RomDatabase::bundled(),
+    };
+    let rom_info = rom_db.lookup(&program);
+    let mut builder = match &rom_info {
+        Some(rom_info) => rom_info.configure(Chip8Builder::new()),
+        None => Chip8Builder::from_preset(&options.preset).expect("Unknown platform preset"),
+    };
+    if options.write_protection {
+        builder = builder.with_write_protection();
+    }
+    if let Some(timing) = options.timing {
+        builder = builder.with_timing(timing);
+    }
+    if let Some(tickrate) = options.tickrate {
+        builder = builder.with_tickrate(tickrate);
+    }
+    let play_movie = options.play_movie.as_ref().map(|path| {
+        let movie = load_movie(path);
+        movie.check_rom(&program).unwrap();
+        movie
+    });
+    //Movies are recorded with a known seed so they replay the same random numbers.
+    let seed = match &play_movie {
+        Some(movie) => movie.seed,
+        None => options.seed.unwrap_or_else(rand::random),
+    };
+    if let Some(movie) = &play_movie {
+        builder = movie.configure(builder);
+    }
+    let mut chip_8: Chip8 = builder
+        .with_seed(seed)
+        .with_program(&program)
+        .build()
+        .unwrap();
+
+    let title = rom_info
+        .as_ref()
+        .map_or(options.rom.as_str(), |rom_info| &rom_info.title);
+    let mut record_movie = options
+        .record_movie
+        .as_ref()
+        .map(|_| Movie::new(&program, &chip_8, seed));
+    //Frames emulated so far, the clock movies are recorded against.
+    let mut frame = 0u32;
+    let mut palette = PALETTE;
+    let mut keymap = HashMap::new();
+    if let Some(rom_info) = &rom_info {
+        for (colour, rgb) in palette.iter_mut().zip(&rom_info.colours) {
+            *colour = Color::RGB(rgb[0], rgb[1], rgb[2]);
+        }
+        for (button, key) in rom_info.keys.iter() {
+            if let Some(keycode) = button_to_sdl_key(button) {
+                keymap.insert(keycode, *key as usize);
+            }
+        }
+    }
+
     let sdl_context: Sdl = sdl2::init().unwrap();
     let video_subsystem: VideoSubsystem = sdl_context.video().unwrap();
     let window = video_subsystem
         .window(
-            "Rust Chip-8;",
+            &format!("Rust Chip-8; {title}"),
             DISPLAY_WIDTH as u32 * PIXEL_SIZE,
             DISPLAY_HEIGHT as u32 * PIXEL_SIZE,
         )
         .position_centered()
         .build();
 
-    let mut canvas: WindowCanvas = window.unwrap().into_canvas().build().unwrap();
+    let mut canvas_builder = window.unwrap().into_canvas();
+    if options.vsync {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    let mut canvas: WindowCanvas = canvas_builder.build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let program = load_program_bytes();
-    let mut chip_8: Chip8 = Chip8Builder::new()
-        .with_program(&program)
-        .with_vf_reset_quirk()
-        .with_jumping_quirk()
-        .build()
-        .unwrap();
+    let audio_subsystem: AudioSubsystem = sdl_context.audio().unwrap();
+    let mut audio_device = open_buzzer(
+        &audio_subsystem,
+        options.buzzer_pitch,
+        options.buzzer_volume,
+    );
+    audio_device.resume();
+
+    let mut rewind = Rewind::new(REWIND_FRAMES);
+    let mut rewinding = false;
+    let mut debugger = Debugger::new();
+    for breakpoint in options.breakpoints.iter() {
+        debugger.add_breakpoint(*breakpoint);
+    }
+    for watchpoint in options.watchpoints.iter() {
+        debugger.add_watchpoint(*watchpoint);
+    }
+    let mut gdb_stub = options.gdb_port.map(|port| {
+        let gdb_stub = GdbStub::bind(port).expect("Could not listen for gdb");
+        println!("Waiting for gdb on {}", gdb_stub.local_addr().unwrap());
+        gdb_stub
+    });
+    let mut was_paused = false;
+    let mut playing = play_movie.is_some();
+    let in_movie = play_movie.is_some() || record_movie.is_some();
+    let mut scheduler = Scheduler::new();
+    if let Some(max_catch_up) = options.max_catch_up {
+        scheduler = scheduler.with_max_catch_up(max_catch_up);
+    }
 
     'running: loop {
         for event in event_pump.poll_iter() {
@@ -45,63 +241,332 @@ fn main() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                //Rewinding and loading states would desync a movie from its frame count.
                 KeyDown {
-                    keycode: Some(key), ..
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if !in_movie => rewinding = true,
+                KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if !in_movie => {
+                    //Keys held in the rewound frame would otherwise stay stuck down.
+                    for key in 0..0x10 {
+                        chip_8.unset_key(key);
+                    }
+                    rewinding = false;
+                }
+                KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    repeat,
+                    ..
                 } => {
-                    if let Some(key) = sdl_key_to_ch8_key(key) {
-                        chip_8.set_key(key);
+                    if let Some(slot) = save_slot(key) {
+                        let state_path = format!("{rom_path}.state{slot}");
+                        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                        if shift {
+                            save_state(&chip_8, &state_path);
+                        } else if in_movie {
+                            println!("Save states can't be loaded while a movie is running");
+                        } else {
+                            load_state(&mut chip_8, &state_path);
+                        }
+                    } else if is_debugger_key(key) {
+                        //Stepping runs instructions outside of any frame, pausing alone is safe.
+                        if in_movie && key != Keycode::P {
+                            println!(
+                                "Stepping and breakpoints are disabled while a movie is running"
+                            );
+                        } else {
+                            handle_debugger_key(key, &mut debugger, &mut chip_8);
+                        }
+                    } else if let Some(key) = sdl_key_to_ch8_key(key, &keymap) {
+                        if !playing {
+                            chip_8.set_key(key);
+                        }
+                        if let Some(movie) = record_movie.as_mut().filter(|_| !repeat) {
+                            movie.record(frame, key, true);
+                        }
                     }
                 }
                 KeyUp {
                     keycode: Some(key), ..
                 } => {
-                    if let Some(key) = sdl_key_to_ch8_key(key) {
-                        chip_8.unset_key(key);
+                    if let Some(key) = sdl_key_to_ch8_key(key, &keymap) {
+                        if !playing {
+                            chip_8.unset_key(key);
+                        }
+                        if let Some(movie) = record_movie.as_mut() {
+                            movie.record(frame, key, false);
+                        }
                     }
                 }
                 _ => {}
             }
         }
 
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.set_draw_color(palette[0]);
         canvas.clear();
-        canvas.set_draw_color(Color::RGB(0, 255, 0));
 
-        let display_buffer = chip_8.get_display_buffer();
-        let pixel_size = if chip_8.is_high_res() {
-            PIXEL_SIZE
-        } else {
-            PIXEL_SIZE * 2
-        } as i32;
-        for (x, collumn) in display_buffer.iter().enumerate() {
-            for (y, pixel) in collumn.iter().enumerate() {
-                if pixel {
-                    canvas
-                        .fill_rect(Rect::new(
-                            x as i32 * pixel_size,
-                            y as i32 * pixel_size,
-                            pixel_size as u32,
-                            pixel_size as u32,
-                        ))
-                        .unwrap();
+        for x in 0..DISPLAY_WIDTH {
+            for y in 0..DISPLAY_HEIGHT {
+                let colour = chip_8.get_pixel(x, y);
+                if colour == 0 {
+                    continue;
                 }
+                canvas.set_draw_color(palette[colour]);
+                canvas
+                    .fill_rect(Rect::new(
+                        x as i32 * PIXEL_SIZE as i32,
+                        y as i32 * PIXEL_SIZE as i32,
+                        PIXEL_SIZE,
+                        PIXEL_SIZE,
+                    ))
+                    .unwrap();
             }
         }
-        chip_8.dec_delay_reg();
-        for _ in 0..1000 {
-            let emu_res = chip_8.execute_next();
-            if let Err(err) = emu_res {
-                println!("{err}");
+        if let Some(gdb_stub) = gdb_stub.as_mut() {
+            if let Err(err) = gdb_stub.poll(&mut chip_8, &mut debugger) {
+                println!("gdb connection lost: {err}");
+            }
+        }
+        let frames_due = scheduler.tick();
+        if rewinding {
+            for _ in 0..frames_due as usize * options.rewind_speed {
+                rewind.rewind(&mut chip_8);
+            }
+            audio_device.lock().active = false;
+        } else if debugger.is_paused() {
+            audio_device.lock().active = false;
+        } else {
+            for _ in 0..frames_due {
+                if debugger.is_paused() || chip_8.is_halted() {
+                    break;
+                }
+                if let Some(movie) = play_movie.as_ref().filter(|_| playing) {
+                    movie.apply(frame, &mut chip_8);
+                }
+                if let Some(reason) = debugger.run_frame(&mut chip_8) {
+                    print_stop(&reason, &chip_8);
+                    if let Some(gdb_stub) = gdb_stub.as_mut() {
+                        gdb_stub.report_stop(&reason).ok();
+                    }
+                }
+                frame += 1;
+                if let Some(movie) = record_movie.as_mut() {
+                    movie.set_frames(frame);
+                }
+                if playing
+                    && play_movie
+                        .as_ref()
+                        .is_some_and(|movie| frame >= movie.frames)
+                {
+                    println!("Movie finished after {frame} frames");
+                    playing = false;
+                }
+                if !in_movie {
+                    rewind.push(&chip_8);
+                }
             }
+            let mut buzzer = audio_device.lock();
+            buzzer.active = chip_8.is_buzzer_active();
+            if chip_8.get_platform() == Platform::XoChip {
+                buzzer.sync_audio_pattern(&chip_8);
+            }
+        }
+        if debugger.is_paused() != was_paused {
+            was_paused = debugger.is_paused();
+            let paused = if was_paused { " [paused]" } else { "" };
+            canvas
+                .window_mut()
+                .set_title(&format!("Rust Chip-8; {title}{paused}"))
+                .unwrap();
         }
         canvas.present();
-        thread::sleep(time::Duration::from_millis(16));
+        if chip_8.is_halted() {
+            if let Some(gdb_stub) = gdb_stub.as_mut() {
+                gdb_stub.report_exit().ok();
+            }
+            break 'running;
+        }
+        //With vsync present() already waited for the display.
+        if !options.vsync {
+            scheduler.wait();
+        }
+    }
+    if let (Some(movie), Some(path)) = (&record_movie, &options.record_movie) {
+        save_movie(movie, path);
+    }
+}
+
+fn open_buzzer(audio_subsystem: &AudioSubsystem, pitch: f32, volume: f32) -> AudioDevice<Buzzer> {
+    let desired_spec = AudioSpecDesired {
+        freq: Some(AUDIO_SAMPLE_RATE),
+        channels: Some(1),
+        samples: None,
+    };
+    audio_subsystem
+        .open_playback(None, &desired_spec, |spec| {
+            Buzzer::new(pitch, volume, spec.freq)
+        })
+        .unwrap()
+}
+
+fn print_stop(reason: &StopReason, chip_8: &Chip8) {
+    println!("{reason}\n{}", state_view(chip_8));
+}
+
+//P pauses/resumes, N steps, O steps over a call, U steps out and B toggles a breakpoint at PC.
+fn is_debugger_key(key: Keycode) -> bool {
+    matches!(
+        key,
+        Keycode::P | Keycode::N | Keycode::O | Keycode::U | Keycode::B
+    )
+}
+
+fn handle_debugger_key(key: Keycode, debugger: &mut Debugger, chip_8: &mut Chip8) {
+    match key {
+        Keycode::P if debugger.is_paused() => debugger.resume(),
+        Keycode::P => {
+            debugger.pause();
+            print_stop(&StopReason::Paused, chip_8);
+        }
+        Keycode::N if debugger.is_paused() => {
+            let reason = debugger.step(chip_8);
+            print_stop(&reason, chip_8);
+        }
+        Keycode::O if debugger.is_paused() => {
+            if let Some(reason) = debugger.step_over(chip_8) {
+                print_stop(&reason, chip_8);
+            }
+        }
+        Keycode::U if debugger.is_paused() => debugger.step_out(chip_8),
+        Keycode::B => {
+            let pc = chip_8.get_pc();
+            let set = if debugger.toggle_breakpoint(pc) {
+                "set"
+            } else {
+                "cleared"
+            };
+            println!("Breakpoint {set} at {pc:#06x}");
+        }
+        _ => {}
     }
 }
 
-fn load_program_bytes() -> Box<[u8]> {
-    let args: Box<[String]> = std::env::args().collect();
-    let path = format!("rom/{}", args.get(1).expect("No argument given for ROM."));
+//Usage: chip8 <rom> [--platform <preset>] [--db <chip-8-database dir>]
+//             [--break <pc>|<condition>|<pc>:<condition>]...
+//             [--watch <r|w|rw>:<addr>[-<end>]]... [--write-protect] [--gdb <port>]
+//             [--seed <n>] [--record <movie>] [--play <movie>] [--vsync]
+//             [--timing tickrate|cosmac-vip] [--tickrate <n>] [--max-catch-up <frames>]
+//             [--pitch <hz>] [--volume <0.0-1.0>] [--rewind-speed <frames>]
+fn parse_args() -> Options {
+    let mut args = std::env::args().skip(1);
+    let mut options = Options {
+        rom: String::new(),
+        preset: DEFAULT_PRESET.to_string(),
+        db_dir: None,
+        breakpoints: Vec::new(),
+        watchpoints: Vec::new(),
+        write_protection: false,
+        gdb_port: None,
+        seed: None,
+        record_movie: None,
+        play_movie: None,
+        vsync: false,
+        timing: None,
+        tickrate: None,
+        max_catch_up: None,
+        buzzer_pitch: DEFAULT_BUZZER_PITCH,
+        buzzer_volume: DEFAULT_BUZZER_VOLUME,
+        rewind_speed: DEFAULT_REWIND_SPEED,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => options.preset = args.next().expect("No preset given for --platform"),
+            "--db" => options.db_dir = Some(args.next().expect("No path given for --db").into()),
+            "--break" => {
+                let breakpoint = args.next().expect("No breakpoint given for --break");
+                options
+                    .breakpoints
+                    .push(breakpoint.parse().expect("Invalid breakpoint"));
+            }
+            "--watch" => {
+                let watchpoint = args.next().expect("No watchpoint given for --watch");
+                options
+                    .watchpoints
+                    .push(watchpoint.parse().expect("Invalid watchpoint"));
+            }
+            "--write-protect" => options.write_protection = true,
+            "--vsync" => options.vsync = true,
+            "--timing" => {
+                let timing = args.next().expect("No timing given for --timing");
+                options.timing = Some(timing.parse().unwrap_or_else(|err| panic!("{err}")));
+            }
+            "--tickrate" => {
+                let tickrate = args.next().expect("No tickrate given for --tickrate");
+                options.tickrate = Some(tickrate.parse().expect("Invalid tickrate"));
+            }
+            "--max-catch-up" => {
+                let frames = args.next().expect("No frames given for --max-catch-up");
+                options.max_catch_up = Some(frames.parse().expect("Invalid frame count"));
+            }
+            "--pitch" => {
+                let pitch = args.next().expect("No pitch given for --pitch");
+                options.buzzer_pitch = pitch.parse().expect("Invalid pitch");
+                if !options.buzzer_pitch.is_finite() || options.buzzer_pitch <= 0.0 {
+                    panic!("Pitch must be above 0Hz");
+                }
+            }
+            "--volume" => {
+                let volume = args.next().expect("No volume given for --volume");
+                options.buzzer_volume = volume.parse().expect("Invalid volume");
+                if !(0.0..=1.0).contains(&options.buzzer_volume) {
+                    panic!("Volume must be between 0.0 and 1.0");
+                }
+            }
+            "--rewind-speed" => {
+                let speed = args.next().expect("No speed given for --rewind-speed");
+                options.rewind_speed = speed.parse().expect("Invalid rewind speed");
+                if options.rewind_speed == 0 {
+                    panic!("Rewind speed must be at least 1");
+                }
+            }
+            "--gdb" => {
+                let port = args.next().expect("No port given for --gdb");
+                options.gdb_port = Some(port.parse().expect("Invalid gdb port"));
+            }
+            "--record" => {
+                let path = args.next().expect("No path given for --record");
+                options.record_movie = Some(path.into());
+            }
+            "--play" => {
+                let path = args.next().expect("No path given for --play");
+                options.play_movie = Some(path.into());
+            }
+            "--seed" => {
+                let seed = args.next().expect("No seed given for --seed");
+                options.seed = Some(seed.parse().expect("Invalid seed"));
+            }
+            _ => options.rom = arg,
+        }
+    }
+    if options.rom.is_empty() {
+        panic!("No argument given for ROM.");
+    }
+    //Breakpoints and gdb stop emulation mid-frame, which a movie can't replay.
+    let in_movie = options.record_movie.is_some() || options.play_movie.is_some();
+    let debugging = !options.breakpoints.is_empty()
+        || !options.watchpoints.is_empty()
+        || options.gdb_port.is_some();
+    if in_movie && debugging {
+        panic!("--break, --watch and --gdb can't be used with --record or --play");
+    }
+    options
+}
+
+fn load_program_bytes(path: &str) -> Box<[u8]> {
     let file = File::open(path).expect("File not found");
     let mut reader = BufReader::new(file);
     let mut buffer = Vec::new();
@@ -109,7 +574,71 @@ fn load_program_bytes() -> Box<[u8]> {
     buffer.into_boxed_slice()
 }
 
-fn sdl_key_to_ch8_key(key: Keycode) -> Option<usize> {
+fn load_movie(path: &Path) -> Movie {
+    let text = fs::read_to_string(path).expect("Could not read movie");
+    text.parse()
+        .unwrap_or_else(|err| panic!("Invalid movie: {err}"))
+}
+
+fn save_movie(movie: &Movie, path: &Path) {
+    match fs::write(path, movie.to_string()) {
+        Ok(()) => println!("Recorded {} frames to {}", movie.frames, path.display()),
+        Err(err) => println!("Could not write {}: {err}", path.display()),
+    }
+}
+
+fn save_state(chip_8: &Chip8, state_path: &str) {
+    if let Err(err) = fs::write(state_path, chip_8.save_state()) {
+        println!("Could not write {state_path}: {err}");
+    }
+}
+
+fn load_state(chip_8: &mut Chip8, state_path: &str) {
+    match fs::read(state_path) {
+        Ok(state) => {
+            if let Err(err) = chip_8.load_state(&state) {
+                println!("{err}");
+            }
+        }
+        Err(err) => println!("Could not read {state_path}: {err}"),
+    }
+}
+
+//F1-F10 load save state slots 1-10, holding shift saves to them instead.
+fn save_slot(key: Keycode) -> Option<usize> {
+    let slot = match key {
+        Keycode::F1 => 1,
+        Keycode::F2 => 2,
+        Keycode::F3 => 3,
+        Keycode::F4 => 4,
+        Keycode::F5 => 5,
+        Keycode::F6 => 6,
+        Keycode::F7 => 7,
+        Keycode::F8 => 8,
+        Keycode::F9 => 9,
+        Keycode::F10 => 10,
+        _ => return None,
+    };
+    Some(slot)
+}
+
+//Database button names, the keypad layout below is always available too.
+fn button_to_sdl_key(button: &str) -> Option<Keycode> {
+    match button {
+        "up" => Some(Keycode::Up),
+        "down" => Some(Keycode::Down),
+        "left" => Some(Keycode::Left),
+        "right" => Some(Keycode::Right),
+        "a" => Some(Keycode::Space),
+        "b" => Some(Keycode::Return),
+        _ => None,
+    }
+}
+
+fn sdl_key_to_ch8_key(key: Keycode, keymap: &HashMap<Keycode, usize>) -> Option<usize> {
+    if let Some(key) = keymap.get(&key) {
+        return Some(*key);
+    }
     match key {
         Keycode::Kp1 => Some(0x1),
         Keycode::Kp2 => Some(0x2),
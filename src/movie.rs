@@ -0,0 +1,315 @@
+//Input movies, key events by frame plus everything needed to replay a session exactly.
+
+use crate::{rom_db::rom_sha1, Chip8, Chip8Builder, Platform, Quirks, Timing};
+use std::{fmt, str::FromStr};
+
+const MOVIE_MAGIC: &str = "chip8-movie";
+const MOVIE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_sha1: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub seed: u64,
+    //Instructions executed per frame.
+    pub tickrate: u32,
+    pub timing: Timing,
+    //Frames recorded, playback ends after this many.
+    pub frames: u32,
+    //In frame order.
+    pub events: Vec<KeyEvent>,
+}
+
+impl Movie {
+    //An empty movie for a session about to be recorded.
+    pub fn new(program: &[u8], chip_8: &Chip8, seed: u64) -> Self {
+        Self {
+            rom_sha1: rom_sha1(program),
+            platform: chip_8.get_platform(),
+            quirks: chip_8.get_quirks(),
+            seed,
+            tickrate: chip_8.get_tickrate(),
+            timing: chip_8.get_timing(),
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    //Records a key change that takes effect before frame runs.
+    pub fn record(&mut self, frame: u32, key: usize, pressed: bool) {
+        debug_assert!(self.events.last().is_none_or(|last| last.frame <= frame));
+        self.events.push(KeyEvent {
+            frame,
+            key,
+            pressed,
+        });
+        self.frames = self.frames.max(frame + 1);
+    }
+
+    //Marks the number of frames that have run, called once per frame while recording.
+    pub fn set_frames(&mut self, frames: u32) {
+        self.frames = frames;
+    }
+
+    //Sets the platform, quirks, seed and timing the movie was recorded with.
+    pub fn configure<'a>(&self, builder: Chip8Builder<'a>) -> Chip8Builder<'a> {
+        builder
+            .with_platform(self.platform)
+            .with_quirks(self.quirks)
+            .with_seed(self.seed)
+            .with_tickrate(self.tickrate)
+            .with_timing(self.timing)
+    }
+
+    pub fn check_rom(&self, program: &[u8]) -> Result<(), String> {
+        let rom_sha1 = rom_sha1(program);
+        if rom_sha1 != self.rom_sha1 {
+            return Err(format!(
+                "Movie was recorded with ROM {}, not {rom_sha1}",
+                self.rom_sha1
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn events_at(&self, frame: u32) -> &[KeyEvent] {
+        let start = self.events.partition_point(|event| event.frame < frame);
+        let end = self.events.partition_point(|event| event.frame <= frame);
+        &self.events[start..end]
+    }
+
+    //Presses and releases the keys for frame, call before running it.
+    pub fn apply(&self, frame: u32, chip_8: &mut Chip8) {
+        for event in self.events_at(frame) {
+            if event.pressed {
+                chip_8.set_key(event.key);
+            } else {
+                chip_8.unset_key(event.key);
+            }
+        }
+    }
+}
+
+fn platform_name(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Chip8 => "chip8",
+        Platform::SuperChip => "schip",
+        Platform::XoChip => "xo-chip",
+    }
+}
+
+//A header of name value lines, then a frame key down|up line per event.
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut quirks = self.quirks;
+        let enabled: Vec<&str> = quirks
+            .named_flags()
+            .into_iter()
+            .filter(|(_, enabled)| **enabled)
+            .map(|(name, _)| name)
+            .collect();
+        let enabled = if enabled.is_empty() {
+            "none".to_string()
+        } else {
+            enabled.join(",")
+        };
+        writeln!(f, "{MOVIE_MAGIC} {MOVIE_VERSION}")?;
+        writeln!(f, "rom {}", self.rom_sha1)?;
+        writeln!(f, "platform {}", platform_name(self.platform))?;
+        writeln!(f, "quirks {enabled}")?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "tickrate {}", self.tickrate)?;
+        writeln!(f, "timing {}", self.timing)?;
+        writeln!(f, "frames {}", self.frames)?;
+        for event in self.events.iter() {
+            let state = if event.pressed { "down" } else { "up" };
+            writeln!(f, "{} {:x} {state}", event.frame, event.key)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(index, line)| (index + 1, line));
+        let mut header = |name: &str| {
+            let (line_no, line) = lines
+                .next()
+                .ok_or_else(|| format!("Missing {name} in movie header"))?;
+            line.strip_prefix(name)
+                .and_then(|value| value.strip_prefix(' '))
+                .map(|value| (line_no, value.trim()))
+                .ok_or_else(|| format!("line {line_no}: expected {name}"))
+        };
+        let (line_no, version) = header(MOVIE_MAGIC)?;
+        if version.parse() != Ok(MOVIE_VERSION) {
+            return Err(format!(
+                "line {line_no}: unsupported movie version {version}"
+            ));
+        }
+        let rom_sha1 = header("rom")?.1.to_ascii_lowercase();
+        let (line_no, platform) = header("platform")?;
+        let platform = [Platform::Chip8, Platform::SuperChip, Platform::XoChip]
+            .into_iter()
+            .find(|known| platform_name(*known) == platform)
+            .ok_or_else(|| format!("line {line_no}: unknown platform {platform}"))?;
+        let (line_no, names) = header("quirks")?;
+        let mut quirks = Quirks::default();
+        for name in names.split(',').filter(|name| *name != "none") {
+            quirks
+                .set(name, true)
+                .map_err(|err| format!("line {line_no}: {err}"))?;
+        }
+        let (line_no, seed) = header("seed")?;
+        let seed = parse_number(line_no, "seed", seed)?;
+        let (line_no, tickrate) = header("tickrate")?;
+        let tickrate = u32::try_from(parse_number(line_no, "tickrate", tickrate)?)
+            .map_err(|_| format!("line {line_no}: tickrate out of range"))?;
+        let (line_no, timing) = header("timing")?;
+        let timing = timing
+            .parse()
+            .map_err(|err| format!("line {line_no}: {err}"))?;
+        let (line_no, frames) = header("frames")?;
+        let frames = u32::try_from(parse_number(line_no, "frames", frames)?)
+            .map_err(|_| format!("line {line_no}: frames out of range"))?;
+        let mut events = Vec::new();
+        for (line_no, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            let event = parse_event(line)
+                .ok_or_else(|| format!("line {line_no}: invalid key event {line}"))?;
+            if events
+                .last()
+                .is_some_and(|last: &KeyEvent| last.frame > event.frame)
+            {
+                return Err(format!("line {line_no}: key event out of order"));
+            }
+            events.push(event);
+        }
+        Ok(Self {
+            rom_sha1,
+            platform,
+            quirks,
+            seed,
+            tickrate,
+            timing,
+            frames,
+            events,
+        })
+    }
+}
+
+fn parse_number(line_no: usize, name: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("line {line_no}: invalid {name} {value}"))
+}
+
+fn parse_event(line: &str) -> Option<KeyEvent> {
+    let mut fields = line.split_whitespace();
+    let frame = fields.next()?.parse().ok()?;
+    let key = usize::from_str_radix(fields.next()?, 16).ok()?;
+    let pressed = match fields.next()? {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+    if key > 0xF || fields.next().is_some() {
+        return None;
+    }
+    Some(KeyEvent {
+        frame,
+        key,
+        pressed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Rolls a random number each instruction and counts instructions run with key 0 held.
+    const PROGRAM: [u8; 8] = [0xC0, 0xFF, 0xE1, 0xA1, 0x72, 0x01, 0x12, 0x00];
+    const FRAMES: u32 = 20;
+
+    fn builder() -> Chip8Builder<'static> {
+        Chip8Builder::from_preset("cosmac-vip")
+            .expect("Known preset")
+            .with_program(&PROGRAM)
+    }
+
+    fn run_frame(chip_8: &mut Chip8) {
+        chip_8
+            .run_frame()
+            .expect("Program only uses valid instructions");
+    }
+
+    #[test]
+    fn format_parse_round_trip() {
+        let chip_8 = builder()
+            .with_seed(3)
+            .build()
+            .expect("Program fits in memory");
+        let mut movie = Movie::new(&PROGRAM, &chip_8, 3);
+        movie.record(2, 0x0, true);
+        movie.record(2, 0xA, true);
+        movie.record(9, 0x0, false);
+        movie.set_frames(12);
+        let parsed: Movie = movie.to_string().parse().expect("Formatted movie parses");
+        assert_eq!(parsed, movie);
+        assert!(parsed.check_rom(&PROGRAM).is_ok());
+        assert!(parsed.check_rom(&PROGRAM[..6]).is_err());
+
+        let newer = movie
+            .to_string()
+            .replacen("chip8-movie 1", "chip8-movie 2", 1);
+        assert!(newer.parse::<Movie>().is_err());
+    }
+
+    #[test]
+    fn replay_is_deterministic() {
+        let seed = 11;
+        let mut recording = builder()
+            .with_seed(seed)
+            .with_timing(Timing::CosmacVip)
+            .build()
+            .expect("Program fits in memory");
+        let mut movie = Movie::new(&PROGRAM, &recording, seed);
+        for frame in 0..FRAMES {
+            match frame {
+                3 => {
+                    movie.record(frame, 0x0, true);
+                    recording.set_key(0x0);
+                }
+                8 => {
+                    movie.record(frame, 0x0, false);
+                    recording.unset_key(0x0);
+                }
+                _ => {}
+            }
+            run_frame(&mut recording);
+            movie.set_frames(frame + 1);
+        }
+        assert_ne!(recording.get_v_regs()[2], 0);
+
+        let movie: Movie = movie.to_string().parse().expect("Formatted movie parses");
+        //Configured from the movie rather than the builder's defaults.
+        let mut replay = movie
+            .configure(builder().with_tickrate(1))
+            .build()
+            .expect("Program fits in memory");
+        for frame in 0..movie.frames {
+            movie.apply(frame, &mut replay);
+            run_frame(&mut replay);
+        }
+        assert_eq!(replay.save_state(), recording.save_state());
+    }
+}
@@ -0,0 +1,331 @@
+//Execution profiles of hot addresses, routines and instruction classes, with a memory heatmap.
+
+use crate::{
+    disassembler::Disassembler, screenshot::encode_png, AccessKind, Chip8, EmuErr, Opcode,
+    OpcodeClass, PG_START,
+};
+use std::{collections::HashMap, fmt::Write};
+
+const CLASSES: [(OpcodeClass, &str); 6] = [
+    (OpcodeClass::Flow, "flow"),
+    (OpcodeClass::Skip, "skip"),
+    (OpcodeClass::Alu, "alu"),
+    (OpcodeClass::Memory, "memory"),
+    (OpcodeClass::Display, "display"),
+    (OpcodeClass::Io, "io"),
+];
+//Addresses per heatmap row.
+const HEATMAP_WIDTH: usize = 64;
+//Heatmap pixels per address along each side.
+const HEATMAP_SCALE: usize = 4;
+//The classic 4K address space, the heatmap only covers XO-CHIP's 64K when asked to.
+const HEATMAP_DEFAULT_LEN: usize = 0x1000;
+//Routines and addresses listed in reports.
+const REPORT_LEN: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RoutineStats {
+    pub calls: u64,
+    //Executed while the routine was innermost, callees not included.
+    pub instructions: u64,
+}
+
+pub struct Profiler {
+    executed: Vec<u64>,
+    //Per byte, as each instruction covers 2 or 4 of them.
+    executed_bytes: Vec<u64>,
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+    routines: HashMap<u16, RoutineStats>,
+    classes: [u64; CLASSES.len()],
+    total: u64,
+}
+
+impl Profiler {
+    pub fn new(chip_8: &Chip8) -> Self {
+        let memory_size = chip_8.get_memory().len();
+        Self {
+            executed: vec![0; memory_size],
+            executed_bytes: vec![0; memory_size],
+            reads: vec![0; memory_size],
+            writes: vec![0; memory_size],
+            routines: HashMap::new(),
+            classes: [0; CLASSES.len()],
+            total: 0,
+        }
+    }
+
+    //Executes the next instruction, counting it.
+    pub fn execute_next(&mut self, chip_8: &mut Chip8) -> Result<(), EmuErr> {
+        let pc = chip_8.get_pc();
+        let routine = current_routine(chip_8);
+        let cycles = chip_8.get_cycles();
+        chip_8.execute_next()?;
+        //Every instruction costs something, nothing ran if the machine was waiting or exited.
+        if chip_8.get_cycles() == cycles {
+            return Ok(());
+        }
+        let Ok(opcode) = Opcode::decode(fetch(chip_8, pc), chip_8.get_platform()) else {
+            return Ok(());
+        };
+        self.total += 1;
+        self.executed[pc as usize] += 1;
+        let end = (pc as usize + opcode.size()).min(self.executed_bytes.len());
+        for executed in self.executed_bytes[pc as usize..end].iter_mut() {
+            *executed += 1;
+        }
+        for access in chip_8.get_mem_accesses() {
+            let counts = match access.kind {
+                AccessKind::Read => &mut self.reads,
+                AccessKind::Write => &mut self.writes,
+            };
+            let end = (access.addr as usize + access.len as usize).min(counts.len());
+            for count in counts[access.addr as usize..end].iter_mut() {
+                *count += 1;
+            }
+        }
+        self.routines.entry(routine).or_default().instructions += 1;
+        if let Opcode::Call { nnn } = opcode {
+            self.routines.entry(nnn).or_default().calls += 1;
+        }
+        let class = CLASSES
+            .iter()
+            .position(|(class, _)| *class == opcode.class())
+            .expect("Every class is listed");
+        self.classes[class] += 1;
+        Ok(())
+    }
+
+    //One 60Hz frame like Chip8::run_frame, counting every instruction.
+    pub fn run_frame(&mut self, chip_8: &mut Chip8) -> Result<(), EmuErr> {
+        chip_8.start_frame();
+        while !chip_8.is_frame_done() {
+            self.execute_next(chip_8)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_total(&self) -> u64 {
+        self.total
+    }
+
+    //Times the instruction at addr was executed.
+    pub fn get_executed(&self, addr: u16) -> u64 {
+        self.executed.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    //Times the byte at addr was executed as part of an instruction.
+    pub fn get_byte_executions(&self, addr: u16) -> u64 {
+        self.executed_bytes.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    //Times the byte at addr was read by an instruction.
+    pub fn get_reads(&self, addr: u16) -> u64 {
+        self.reads.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    //Times the byte at addr was written by an instruction.
+    pub fn get_writes(&self, addr: u16) -> u64 {
+        self.writes.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    //Adds the counts from another run, such as the same ROM played with different inputs.
+    pub fn merge(&mut self, other: &Profiler) {
+        for (counts, other_counts) in [
+            (&mut self.executed, &other.executed),
+            (&mut self.executed_bytes, &other.executed_bytes),
+            (&mut self.reads, &other.reads),
+            (&mut self.writes, &other.writes),
+        ] {
+            if counts.len() < other_counts.len() {
+                counts.resize(other_counts.len(), 0);
+            }
+            for (count, other_count) in counts.iter_mut().zip(other_counts) {
+                *count += other_count;
+            }
+        }
+        for (addr, other_stats) in other.routines.iter() {
+            let stats = self.routines.entry(*addr).or_default();
+            stats.calls += other_stats.calls;
+            stats.instructions += other_stats.instructions;
+        }
+        for (count, other_count) in self.classes.iter_mut().zip(other.classes) {
+            *count += other_count;
+        }
+        self.total += other.total;
+    }
+
+    //Routines by entry point, busiest first.
+    pub fn hot_routines(&self) -> Vec<(u16, RoutineStats)> {
+        let mut routines: Vec<_> = self
+            .routines
+            .iter()
+            .map(|(addr, stats)| (*addr, *stats))
+            .collect();
+        routines.sort_by(|(a_addr, a), (b_addr, b)| {
+            b.instructions.cmp(&a.instructions).then(a_addr.cmp(b_addr))
+        });
+        routines
+    }
+
+    //Executed addresses, hottest first.
+    pub fn hot_addresses(&self) -> Vec<(u16, u64)> {
+        let mut addresses: Vec<_> = (0..self.executed.len())
+            .filter(|addr| self.executed[*addr] > 0)
+            .map(|addr| (addr as u16, self.executed[addr]))
+            .collect();
+        addresses.sort_by(|(a_addr, a), (b_addr, b)| b.cmp(a).then(a_addr.cmp(b_addr)));
+        addresses
+    }
+
+    pub fn class_counts(&self) -> Vec<(OpcodeClass, u64)> {
+        CLASSES
+            .iter()
+            .zip(self.classes)
+            .map(|((class, _), count)| (*class, count))
+            .collect()
+    }
+
+    //Ranked routines, the instruction class histogram and the hottest addresses.
+    pub fn report(&self, chip_8: &Chip8) -> String {
+        let share = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let mut report = format!("Executed {} instructions\n\n", self.total);
+        writeln!(report, "routine      calls  instructions   share").unwrap();
+        for (addr, stats) in self.hot_routines().into_iter().take(REPORT_LEN) {
+            writeln!(
+                report,
+                "{addr:#7x} {:>10} {:>13} {:>6.1}%",
+                stats.calls,
+                stats.instructions,
+                share(stats.instructions)
+            )
+            .unwrap();
+        }
+        writeln!(report, "\nclass     instructions   share").unwrap();
+        for ((_, name), count) in CLASSES.iter().zip(self.classes) {
+            writeln!(report, "{name:<8} {count:>13} {:>6.1}%", share(count)).unwrap();
+        }
+        writeln!(report, "\naddress  executed   share  instruction").unwrap();
+        let disassembler = Disassembler::new(chip_8.get_platform());
+        for (addr, count) in self.hot_addresses().into_iter().take(REPORT_LEN) {
+            let memory = chip_8.get_memory();
+            let mnemonic = disassembler
+                .instruction(&memory[addr as usize..])
+                .map_or_else(|| "-".to_string(), |(text, _)| text);
+            writeln!(
+                report,
+                "{addr:#7x} {count:>9} {:>6.1}%  {mnemonic}",
+                share(count)
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    //Disassembly of program with how often each instruction ran, or each data line was read.
+    pub fn annotated_listing(&self, chip_8: &Chip8, program: &[u8]) -> String {
+        let mut listing = String::new();
+        for line in Disassembler::new(chip_8.get_platform()).disassemble(program) {
+            if let Some(label) = &line.label {
+                writeln!(listing, "{:>10}  : {label}", "").unwrap();
+            }
+            let addr = line.addr;
+            let (count, text) = match line.text {
+                Some(text) => (self.get_executed(addr), text),
+                None => {
+                    let reads = (addr..addr + line.bytes.len() as u16)
+                        .map(|addr| self.get_reads(addr))
+                        .sum();
+                    let bytes: Vec<_> = line
+                        .bytes
+                        .iter()
+                        .map(|byte| format!("{byte:#04x}"))
+                        .collect();
+                    (reads, bytes.join(" "))
+                }
+            };
+            writeln!(listing, "{count:>10}  {addr:#05x}  {text}").unwrap();
+        }
+        listing
+    }
+
+    //RGB PNG of addresses 0x000-0xFFF, or of all memory when full_memory is set.
+    pub fn heatmap_png(&self, full_memory: bool) -> Vec<u8> {
+        let len = if full_memory {
+            self.executed_bytes.len()
+        } else {
+            self.executed_bytes.len().min(HEATMAP_DEFAULT_LEN)
+        };
+        let (executed_bytes, reads) = (&self.executed_bytes[..len], &self.reads[..len]);
+        let max_executed = executed_bytes.iter().copied().max().unwrap_or(0);
+        let max_reads = reads.iter().copied().max().unwrap_or(0);
+        let colours: Vec<[u8; 3]> = (0..len)
+            .map(|addr| {
+                let (executed, reads) = (executed_bytes[addr], reads[addr]);
+                if executed > 0 {
+                    let heat = heat(executed, max_executed);
+                    [
+                        0x80 + (0x7F as f64 * heat) as u8,
+                        (0xFF as f64 * heat) as u8,
+                        0,
+                    ]
+                } else if reads > 0 {
+                    let heat = heat(reads, max_reads);
+                    [
+                        0,
+                        (0x40 as f64 * heat) as u8,
+                        0x80 + (0x7F as f64 * heat) as u8,
+                    ]
+                } else {
+                    [0, 0, 0]
+                }
+            })
+            .collect();
+        let width = HEATMAP_WIDTH * HEATMAP_SCALE;
+        let rows = colours.len().div_ceil(HEATMAP_WIDTH);
+        let mut pixels = Vec::with_capacity(width * rows * HEATMAP_SCALE * 3);
+        for row in colours.chunks(HEATMAP_WIDTH) {
+            for _ in 0..HEATMAP_SCALE {
+                for colour in row {
+                    for _ in 0..HEATMAP_SCALE {
+                        pixels.extend_from_slice(colour);
+                    }
+                }
+            }
+        }
+        encode_png(
+            width as u32,
+            (rows * HEATMAP_SCALE) as u32,
+            png::ColorType::Rgb,
+            &pixels,
+        )
+    }
+}
+
+//0.0 to 1.0 on a log scale, counts vary over orders of magnitude.
+fn heat(count: u64, max: u64) -> f64 {
+    if max <= 1 {
+        return 1.0;
+    }
+    (count as f64).ln() / (max as f64).ln()
+}
+
+//Entry point of the innermost routine, the target of the CALL before the top return address.
+fn current_routine(chip_8: &Chip8) -> u16 {
+    let Some(return_addr) = chip_8.get_stack().last() else {
+        return PG_START as u16;
+    };
+    let word = fetch(chip_8, return_addr.wrapping_sub(2));
+    match Opcode::decode(word, chip_8.get_platform()) {
+        Ok(Opcode::Call { nnn }) => nnn,
+        _ => PG_START as u16,
+    }
+}
+
+fn fetch(chip_8: &Chip8, addr: u16) -> u16 {
+    let memory = chip_8.get_memory();
+    match (memory.get(addr as usize), memory.get(addr as usize + 1)) {
+        (Some(high), Some(low)) => u16::from_be_bytes([*high, *low]),
+        _ => 0,
+    }
+}
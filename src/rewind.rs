@@ -0,0 +1,192 @@
+//Ring buffer of per frame save states, older frames are kept as run length encoded deltas.
+
+use crate::Chip8;
+use std::collections::VecDeque;
+
+//Delta tags, full copies are used when the snapshot size changes between frames.
+const DELTA_XOR: u8 = 0x0;
+const DELTA_FULL: u8 = 0x1;
+
+pub struct Rewind {
+    capacity: usize,
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    //Capacity is the number of frames that can be stepped back through.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            current: None,
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, chip_8: &Chip8) {
+        let state = chip_8.save_state();
+        if let Some(previous) = self.current.take() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(encode_delta(&state, &previous));
+        }
+        self.current = Some(state);
+    }
+
+    //Steps back one frame, returns false once the oldest frame has been reached.
+    pub fn rewind(&mut self, chip_8: &mut Chip8) -> bool {
+        let (Some(current), Some(delta)) = (&self.current, self.deltas.pop_back()) else {
+            return false;
+        };
+        let previous = decode_delta(current, &delta);
+        chip_8
+            .load_state(&previous)
+            .expect("Rewind snapshots are created by save_state");
+        self.current = Some(previous);
+        true
+    }
+
+    //Number of frames that can be stepped back through.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+    }
+}
+
+//Encodes target relative to base as runs of (zero count, literal count, literal bytes)
+//over their XOR.
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    if base.len() != target.len() {
+        let mut delta = vec![DELTA_FULL];
+        delta.extend_from_slice(target);
+        return delta;
+    }
+    let xor: Vec<u8> = base.iter().zip(target).map(|(a, b)| a ^ b).collect();
+    let mut delta = vec![DELTA_XOR];
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|byte| **byte == 0).count();
+        i += zeros;
+        let literals = xor[i..].iter().take_while(|byte| **byte != 0).count();
+        write_varint(&mut delta, zeros);
+        write_varint(&mut delta, literals);
+        delta.extend_from_slice(&xor[i..i + literals]);
+        i += literals;
+    }
+    delta
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    if delta[0] == DELTA_FULL {
+        return delta[1..].to_vec();
+    }
+    let mut target = base.to_vec();
+    let mut pos = 1;
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literals = read_varint(delta, &mut pos);
+        for (byte, xor) in target[i..i + literals]
+            .iter_mut()
+            .zip(&delta[pos..pos + literals])
+        {
+            *byte ^= xor;
+        }
+        pos += literals;
+        i += literals;
+    }
+    target
+}
+
+//LEB128 style, 7 bits per byte with the high bit set on all but the last.
+fn write_varint(buffer: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        buffer.push((val as u8 & 0x7F) | 0x80);
+        val >>= 7;
+    }
+    buffer.push(val as u8);
+}
+
+fn read_varint(buffer: &[u8], pos: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+    loop {
+        let byte = buffer[*pos];
+        *pos += 1;
+        val |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return val;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip8Builder;
+
+    fn round_trip(base: &[u8], target: &[u8]) {
+        let delta = encode_delta(base, target);
+        assert_eq!(decode_delta(base, &delta), target);
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let base: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        round_trip(&base, &base);
+        let mut target = base.clone();
+        //Changes at both ends, a run longer than one varint byte and scattered bytes.
+        target[0] ^= 0xFF;
+        for byte in target[100..400].iter_mut() {
+            *byte = byte.wrapping_add(1);
+        }
+        target[500] = !target[500];
+        target[999] ^= 0x01;
+        round_trip(&base, &target);
+        round_trip(&base, &base[..10]);
+        round_trip(&[], &base);
+    }
+
+    #[test]
+    fn delta_of_unchanged_state_is_small() {
+        let base = vec![0xAA; 0x10000];
+        assert!(encode_delta(&base, &base).len() < 8);
+    }
+
+    #[test]
+    fn rewind_restores_earlier_frames() {
+        //Counts up in V0 every instruction.
+        let program = [0x70, 0x01, 0x12, 0x00];
+        let mut chip_8 = Chip8Builder::from_preset("schip-modern")
+            .expect("Known preset")
+            .with_program(&program)
+            .build()
+            .expect("Program fits in memory");
+        let mut rewind = Rewind::new(4);
+        let mut states = Vec::new();
+        for _ in 0..6 {
+            rewind.push(&chip_8);
+            states.push(chip_8.save_state());
+            chip_8
+                .run_frame()
+                .expect("Program only uses valid instructions");
+        }
+        rewind.push(&chip_8);
+        assert_eq!(rewind.len(), 4);
+        for expected in states.iter().rev().take(4) {
+            assert!(rewind.rewind(&mut chip_8));
+            assert_eq!(&chip_8.save_state(), expected);
+        }
+        assert!(!rewind.rewind(&mut chip_8));
+    }
+}
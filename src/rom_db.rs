@@ -0,0 +1,215 @@
+//Per ROM settings looked up by SHA-1 in the chip-8-database, a trimmed copy is bundled.
+
+use crate::{Chip8Builder, Platform, Quirks};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use std::{collections::HashMap, fs, io, path::Path};
+
+const PROGRAMS_JSON: &str = include_str!("../db/programs.json");
+const HASHES_JSON: &str = include_str!("../db/sha1-hashes.json");
+const PLATFORMS_JSON: &str = include_str!("../db/platforms.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    roms: HashMap<String, RomEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RomEntry {
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, DbQuirks>,
+    tickrate: Option<u32>,
+    colors: Option<Colours>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colours {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlatformEntry {
+    id: String,
+    default_tickrate: Option<u32>,
+    #[serde(default)]
+    quirks: DbQuirks,
+}
+
+//Quirk names as used by the database, any that are missing keep the platform value.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+struct DbQuirks {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl DbQuirks {
+    fn apply(&self, quirks: &mut Quirks) {
+        let set = |field: &mut bool, value: Option<bool>| {
+            if let Some(value) = value {
+                *field = value;
+            }
+        };
+        set(&mut quirks.shifting_quirk, self.shift);
+        set(&mut quirks.memory_quirk, self.memory_quirk());
+        set(&mut quirks.clipping_quirk, self.wrap.map(|wrap| !wrap));
+        set(&mut quirks.jumping_quirk, self.jump);
+        set(&mut quirks.display_wait_quirk, self.vblank);
+        set(&mut quirks.vf_reset_quirk, self.logic);
+    }
+
+    //I is incremented unless it's left unchanged, a missing key counts as false if the other is given.
+    //Incrementing by X alone isn't modelled, X + 1 is the closest behaviour.
+    fn memory_quirk(&self) -> Option<bool> {
+        match (self.memory_increment_by_x, self.memory_leave_i_unchanged) {
+            (None, None) => None,
+            (increment_by_x, unchanged) => {
+                Some(increment_by_x.unwrap_or(false) || !unchanged.unwrap_or(false))
+            }
+        }
+    }
+}
+
+//Settings for a ROM found in the database.
+#[derive(Debug, Clone)]
+pub struct RomInfo {
+    pub title: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    //Instructions executed per 60Hz frame.
+    pub tickrate: Option<u32>,
+    //RGB colours for each pixel value, starting with the background.
+    pub colours: Vec<[u8; 3]>,
+    //Named buttons (up, down, left, right, a, b) mapped to CHIP-8 keys.
+    pub keys: HashMap<String, u8>,
+}
+
+impl RomInfo {
+    pub fn configure<'a>(&self, builder: Chip8Builder<'a>) -> Chip8Builder<'a> {
+        let builder = builder
+            .with_platform(self.platform)
+            .with_quirks(self.quirks);
+        match self.tickrate {
+            Some(tickrate) => builder.with_tickrate(tickrate),
+            None => builder,
+        }
+    }
+}
+
+pub struct RomDatabase {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+    platforms: Vec<PlatformEntry>,
+}
+
+impl RomDatabase {
+    pub fn bundled() -> Self {
+        Self::from_json(PROGRAMS_JSON, HASHES_JSON, PLATFORMS_JSON)
+            .expect("Bundled ROM database is valid JSON")
+    }
+
+    pub fn from_json(
+        programs: &str,
+        hashes: &str,
+        platforms: &str,
+    ) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            programs: serde_json::from_str(programs)?,
+            hashes: serde_json::from_str(hashes)?,
+            platforms: serde_json::from_str(platforms)?,
+        })
+    }
+
+    //Loads programs.json, sha1-hashes.json and platforms.json from a database checkout.
+    pub fn from_dir(dir: &Path) -> io::Result<Self> {
+        let read = |name: &str| fs::read_to_string(dir.join(name));
+        Self::from_json(
+            &read("programs.json")?,
+            &read("sha1-hashes.json")?,
+            &read("platforms.json")?,
+        )
+        .map_err(io::Error::from)
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<RomInfo> {
+        let hash = rom_sha1(rom);
+        let program = self.programs.get(*self.hashes.get(&hash)?)?;
+        let rom_entry = program.roms.get(&hash)?;
+
+        //Uses the first listed platform this emulator supports.
+        let (platform_entry, platform) = rom_entry.platforms.iter().find_map(|id| {
+            let platform = platform_from_id(id)?;
+            let entry = self.platforms.iter().find(|entry| &entry.id == id)?;
+            Some((entry, platform))
+        })?;
+
+        let mut quirks = Quirks {
+            half_scroll_quirk: platform == Platform::SuperChip,
+            ..Quirks::default()
+        };
+        platform_entry.quirks.apply(&mut quirks);
+        if let Some(rom_quirks) = rom_entry.quirky_platforms.get(&platform_entry.id) {
+            rom_quirks.apply(&mut quirks);
+        }
+
+        let colours = rom_entry
+            .colors
+            .iter()
+            .flat_map(|colours| colours.pixels.iter())
+            .filter_map(|colour| parse_colour(colour))
+            .collect();
+
+        Some(RomInfo {
+            title: program.title.clone(),
+            platform,
+            quirks,
+            tickrate: rom_entry.tickrate.or(platform_entry.default_tickrate),
+            colours,
+            keys: rom_entry.keys.clone(),
+        })
+    }
+}
+
+impl Default for RomDatabase {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+pub fn rom_sha1(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn platform_from_id(id: &str) -> Option<Platform> {
+    match id {
+        "originalChip8" | "hybridVIP" | "modernChip8" => Some(Platform::Chip8),
+        "chip48" | "superchip1" | "superchip" => Some(Platform::SuperChip),
+        "xochip" => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+//Parses "#rrggbb" colours.
+fn parse_colour(colour: &str) -> Option<[u8; 3]> {
+    let hex = colour.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
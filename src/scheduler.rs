@@ -0,0 +1,66 @@
+//Fixed timestep 60Hz frame pacing for frontends.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+//Timer and display refresh rate of every CHIP-8 platform.
+pub const FRAME_RATE: u32 = 60;
+//Frames run in one go before the scheduler gives up catching up.
+const DEFAULT_MAX_CATCH_UP: u32 = 4;
+
+pub struct Scheduler {
+    frame_time: Duration,
+    max_catch_up: u32,
+    last_tick: Instant,
+    //Time elapsed that hasn't been spent on frames yet, always under frame_time after a tick.
+    accumulator: Duration,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            frame_time: Duration::from_secs(1) / FRAME_RATE,
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+            last_tick: Instant::now(),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn with_max_catch_up(mut self, max_catch_up: u32) -> Self {
+        self.max_catch_up = max_catch_up.max(1);
+        self
+    }
+
+    //Number of frames to run now, 0 if the next one isn't due yet.
+    pub fn tick(&mut self) -> u32 {
+        let now = Instant::now();
+        self.accumulator += now - self.last_tick;
+        self.last_tick = now;
+        let due = (self.accumulator.as_nanos() / self.frame_time.as_nanos()) as u32;
+        if due > self.max_catch_up {
+            self.accumulator = Duration::ZERO;
+            return self.max_catch_up;
+        }
+        self.accumulator -= self.frame_time * due;
+        due
+    }
+
+    //Time left until the next frame is due.
+    pub fn until_next_frame(&self) -> Duration {
+        let pending = self.accumulator + self.last_tick.elapsed();
+        self.frame_time.saturating_sub(pending)
+    }
+
+    //Sleeps until the next frame is due, for frontends without vsync.
+    pub fn wait(&self) {
+        thread::sleep(self.until_next_frame());
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
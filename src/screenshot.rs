@@ -0,0 +1,100 @@
+//Encodes the full 128x64 display buffer as PNG, PBM or ASCII art.
+
+use crate::{Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use std::{path::Path, str::FromStr};
+
+//Grey levels for background, plane 1, plane 2 and both planes.
+const GREYS: [u8; 4] = [0x00, 0xFF, 0xAA, 0x55];
+//ASCII art characters, in the same order.
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Pbm,
+    Ascii,
+}
+
+impl ImageFormat {
+    //Format matching the file extension, ASCII art for .txt.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "pbm" => Ok(ImageFormat::Pbm),
+            "ascii" | "txt" => Ok(ImageFormat::Ascii),
+            _ => Err(format!("Unknown image format {s}")),
+        }
+    }
+}
+
+pub fn encode(chip_8: &Chip8, format: ImageFormat) -> Vec<u8> {
+    match format {
+        ImageFormat::Png => png(chip_8),
+        ImageFormat::Pbm => pbm(chip_8),
+        ImageFormat::Ascii => ascii(chip_8).into_bytes(),
+    }
+}
+
+//One line per row, '.' for unlit pixels.
+pub fn ascii(chip_8: &Chip8) -> String {
+    let mut art = String::with_capacity((DISPLAY_WIDTH + 1) * DISPLAY_HEIGHT);
+    for y in 0..DISPLAY_HEIGHT {
+        art.extend((0..DISPLAY_WIDTH).map(|x| ASCII_PIXELS[chip_8.get_pixel(x, y)]));
+        art.push('\n');
+    }
+    art
+}
+
+//Binary (P4) bitmap, rows are packed 8 pixels to a byte with 1 as black.
+pub fn pbm(chip_8: &Chip8) -> Vec<u8> {
+    let mut image = format!("P4\n{DISPLAY_WIDTH} {DISPLAY_HEIGHT}\n").into_bytes();
+    for y in 0..DISPLAY_HEIGHT {
+        for x_byte in (0..DISPLAY_WIDTH).step_by(8) {
+            let byte = (0..8).fold(0u8, |byte, bit| {
+                let lit = chip_8.get_pixel(x_byte + bit, y) != 0;
+                byte | (lit as u8) << (7 - bit)
+            });
+            image.push(byte);
+        }
+    }
+    image
+}
+
+//8 bit greyscale PNG.
+pub fn png(chip_8: &Chip8) -> Vec<u8> {
+    let pixels: Vec<u8> = (0..DISPLAY_HEIGHT)
+        .flat_map(|y| (0..DISPLAY_WIDTH).map(move |x| GREYS[chip_8.get_pixel(x, y)]))
+        .collect();
+    encode_png(
+        DISPLAY_WIDTH as u32,
+        DISPLAY_HEIGHT as u32,
+        png::ColorType::Grayscale,
+        &pixels,
+    )
+}
+
+//8 bit PNG of width x height pixels, stored row by row.
+pub(crate) fn encode_png(
+    width: u32,
+    height: u32,
+    colour: png::ColorType,
+    pixels: &[u8],
+) -> Vec<u8> {
+    let mut image = Vec::new();
+    let mut encoder = png::Encoder::new(&mut image, width, height);
+    encoder.set_color(colour);
+    encoder.set_depth(png::BitDepth::Eight);
+    //Writing to a Vec can't fail and the size always matches the header.
+    let mut writer = encoder.write_header().expect("PNG header");
+    writer.write_image_data(pixels).expect("PNG data");
+    writer.finish().expect("PNG end");
+    image
+}
@@ -0,0 +1,363 @@
+//Per instruction execution traces as text or a compact binary format chip8-trace converts.
+
+use crate::{disassembler::Disassembler, Opcode, OpcodeClass, Platform};
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+//Binary traces start with the magic, version and platform, then a record per entry.
+const TRACE_MAGIC: &[u8; 4] = b"C8TR";
+const TRACE_VERSION: u8 = 1;
+//Column the register changes start at in text traces.
+const MNEMONIC_WIDTH: usize = 28;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    //Registers the instruction changed and their new values.
+    pub changed: Vec<(usize, u8)>,
+    pub i_reg: u16,
+    pub stack_depth: u8,
+    //Word following an XO-CHIP F000, the address it loads into I.
+    pub operand: Option<u16>,
+}
+
+impl TraceEntry {
+    //One line of a text trace, without the newline.
+    pub fn to_text(&self, platform: Platform) -> String {
+        let mut bytes = self.opcode.to_be_bytes().to_vec();
+        bytes.extend(
+            self.operand
+                .iter()
+                .flat_map(|operand| operand.to_be_bytes()),
+        );
+        let mnemonic = Disassembler::new(platform)
+            .instruction(&bytes)
+            .map_or_else(|| "-".to_string(), |(text, _)| text);
+        let word = match self.operand {
+            Some(operand) => format!("{:04x}{operand:04x}", self.opcode),
+            None => format!("{:04x}    ", self.opcode),
+        };
+        let mut line = format!(
+            "{:>10} {:#05x} {word}  {mnemonic:<MNEMONIC_WIDTH$}",
+            self.cycle, self.pc
+        );
+        for (reg, val) in self.changed.iter() {
+            write!(line, " V{reg:X}={val:02x}").unwrap();
+        }
+        write!(line, " I={:#05x} SP={}", self.i_reg, self.stack_depth).unwrap();
+        line
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    #[default]
+    Text,
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(TraceFormat::Text),
+            "binary" => Ok(TraceFormat::Binary),
+            _ => Err(format!("Unknown trace format {s}")),
+        }
+    }
+}
+
+//Which instructions are traced, everything by default.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    pub pcs: Option<RangeInclusive<u16>>,
+    //Empty for all classes.
+    pub classes: Vec<OpcodeClass>,
+}
+
+impl TraceFilter {
+    //Words that don't decode have no class, they're only filtered by PC.
+    pub fn matches(&self, pc: u16, opcode: Option<Opcode>) -> bool {
+        self.pcs.as_ref().is_none_or(|pcs| pcs.contains(&pc))
+            && (self.classes.is_empty()
+                || opcode.is_none_or(|opcode| self.classes.contains(&opcode.class())))
+    }
+}
+
+//Writes entries to any io::Write, the first write error stops the trace and is returned by finish.
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    header_written: bool,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(writer: impl Write + 'static, format: TraceFormat) -> Self {
+        Self {
+            writer: Box::new(writer),
+            format,
+            filter: TraceFilter::default(),
+            header_written: false,
+            error: None,
+        }
+    }
+
+    pub fn with_filter(mut self, filter: TraceFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn wants(&self, pc: u16, opcode: Option<Opcode>) -> bool {
+        self.error.is_none() && self.filter.matches(pc, opcode)
+    }
+
+    //Writes the binary header up front, so a trace that matched nothing can still be read.
+    pub fn start(&mut self, platform: Platform) {
+        if let Err(err) = self.write_header(platform) {
+            self.error.get_or_insert(err);
+        }
+    }
+
+    pub fn record(&mut self, platform: Platform, entry: &TraceEntry) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(err) = self.write_entry(platform, entry) {
+            self.error = Some(err);
+        }
+    }
+
+    fn write_header(&mut self, platform: Platform) -> io::Result<()> {
+        if self.format != TraceFormat::Binary || self.header_written {
+            return Ok(());
+        }
+        self.header_written = true;
+        self.writer.write_all(TRACE_MAGIC)?;
+        self.writer
+            .write_all(&[TRACE_VERSION, platform_id(platform)])
+    }
+
+    fn write_entry(&mut self, platform: Platform, entry: &TraceEntry) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => writeln!(self.writer, "{}", entry.to_text(platform)),
+            TraceFormat::Binary => {
+                self.write_header(platform)?;
+                self.writer.write_all(&encode_entry(platform, entry))
+            }
+        }
+    }
+
+    //Flushes the writer, returning the first error hit while tracing.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+fn platform_id(platform: Platform) -> u8 {
+    match platform {
+        Platform::Chip8 => 0,
+        Platform::SuperChip => 1,
+        Platform::XoChip => 2,
+    }
+}
+
+//Whether a record carries the F000 operand, known from the header's platform and the opcode.
+fn has_operand(platform: Platform, opcode: u16) -> bool {
+    platform == Platform::XoChip && opcode == 0xF000
+}
+
+//Little endian cycle, PC, opcode, I, stack depth and changed register mask, then the changed values
+//and for F000 the operand.
+fn encode_entry(platform: Platform, entry: &TraceEntry) -> Vec<u8> {
+    let mut record = Vec::with_capacity(19 + entry.changed.len());
+    record.extend_from_slice(&entry.cycle.to_le_bytes());
+    record.extend_from_slice(&entry.pc.to_le_bytes());
+    record.extend_from_slice(&entry.opcode.to_le_bytes());
+    record.extend_from_slice(&entry.i_reg.to_le_bytes());
+    record.push(entry.stack_depth);
+    let mask = entry
+        .changed
+        .iter()
+        .fold(0u16, |mask, (reg, _)| mask | 1 << reg);
+    record.extend_from_slice(&mask.to_le_bytes());
+    record.extend(entry.changed.iter().map(|(_, val)| *val));
+    if has_operand(platform, entry.opcode) {
+        record.extend_from_slice(&entry.operand.unwrap_or(0).to_le_bytes());
+    }
+    record
+}
+
+//Reads back a binary trace, an iterator over its entries.
+pub struct TraceReader<R: Read> {
+    reader: R,
+    platform: Platform,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 6];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != TRACE_MAGIC {
+            return Err(invalid_data("not a binary trace"));
+        }
+        if header[4] != TRACE_VERSION {
+            return Err(invalid_data("unsupported trace version"));
+        }
+        let platform = match header[5] {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            _ => return Err(invalid_data("unknown platform")),
+        };
+        Ok(Self { reader, platform })
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    fn read_entry(&mut self) -> io::Result<Option<TraceEntry>> {
+        let mut record = [0u8; 17];
+        //A clean end of file can only fall between records.
+        match self.reader.read(&mut record[..1])? {
+            0 => return Ok(None),
+            _ => self.reader.read_exact(&mut record[1..])?,
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([record[at], record[at + 1]]);
+        let mask = u16_at(15);
+        let mut values = vec![0u8; mask.count_ones() as usize];
+        self.reader.read_exact(&mut values)?;
+        let changed = (0..0x10)
+            .filter(|reg| mask & 1 << reg != 0)
+            .zip(values)
+            .collect();
+        let opcode = u16_at(10);
+        let operand = if has_operand(self.platform, opcode) {
+            let mut operand = [0u8; 2];
+            self.reader.read_exact(&mut operand)?;
+            Some(u16::from_le_bytes(operand))
+        } else {
+            None
+        };
+        Ok(Some(TraceEntry {
+            cycle: u64::from_le_bytes(record[..8].try_into().unwrap()),
+            pc: u16_at(8),
+            opcode,
+            changed,
+            i_reg: u16_at(12),
+            stack_depth: record[14],
+            operand,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    //Keeps what the tracer writes readable after the tracer has taken ownership.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn entries() -> Vec<TraceEntry> {
+        vec![
+            TraceEntry {
+                cycle: 0,
+                pc: 0x200,
+                opcode: 0xF000,
+                changed: Vec::new(),
+                i_reg: 0x1234,
+                stack_depth: 0,
+                operand: Some(0x1234),
+            },
+            TraceEntry {
+                cycle: 1,
+                pc: 0x204,
+                opcode: 0x8014,
+                changed: vec![(0x0, 0x10), (0xF, 0x01)],
+                i_reg: 0x1234,
+                stack_depth: 2,
+                operand: None,
+            },
+            TraceEntry {
+                cycle: u64::MAX,
+                pc: 0xFFFE,
+                opcode: 0x5123,
+                changed: Vec::new(),
+                i_reg: 0,
+                stack_depth: 16,
+                operand: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn binary_trace_round_trip() {
+        let buffer = SharedBuffer::default();
+        let mut tracer = Tracer::new(buffer.clone(), TraceFormat::Binary);
+        tracer.start(Platform::XoChip);
+        for entry in entries() {
+            tracer.record(Platform::XoChip, &entry);
+        }
+        tracer.finish().expect("Writes to a Vec succeed");
+
+        let data = buffer.0.borrow();
+        let reader = TraceReader::new(data.as_slice()).expect("Header is valid");
+        assert_eq!(reader.platform(), Platform::XoChip);
+        let read: Vec<TraceEntry> = reader
+            .collect::<io::Result<_>>()
+            .expect("Records are valid");
+        assert_eq!(read, entries());
+        assert!(read[0].to_text(Platform::XoChip).contains("f0001234"));
+    }
+
+    #[test]
+    fn binary_trace_rejects_truncation() {
+        let buffer = SharedBuffer::default();
+        let mut tracer = Tracer::new(buffer.clone(), TraceFormat::Binary);
+        tracer.start(Platform::XoChip);
+        tracer.record(Platform::XoChip, &entries()[0]);
+        tracer.finish().expect("Writes to a Vec succeed");
+
+        let data = buffer.0.borrow();
+        let truncated = &data[..data.len() - 1];
+        let mut reader = TraceReader::new(truncated).expect("Header is valid");
+        assert!(reader.next().is_some_and(|entry| entry.is_err()));
+        assert!(TraceReader::new(&data[1..]).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//Runs the bundled test ROMs under every quirk preset and compares the final
+//display against the golden images in tests/golden. Set CHIP8_BLESS=1 to
+//rewrite the golden images after an intended change.
+
+use chip8::{screenshot, Chip8, Chip8Builder, Opcode, Quirks};
+use std::{env, fs, path::PathBuf};
+
+const PRESETS: [&str; 4] = ["cosmac-vip", "schip-legacy", "schip-modern", "xo-chip"];
+const TICKRATE: u32 = 1000;
+//Upper bound on frames, every test finishes well within it.
+const MAX_FRAMES: u32 = 600;
+//The test suite runs the test in 0x1FF straight away instead of showing its menu.
+const SUITE_TEST_ADDR: usize = 0x1FF;
+//Platform the quirks test checks against, skipping its platform menu.
+const SUITE_PLATFORM_ADDR: usize = 0x1FE;
+const SUITE_IBM_LOGO: u8 = 1;
+const SUITE_QUIRKS: u8 = 4;
+
+fn rom(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("rom")
+        .join(name);
+    fs::read(&path).unwrap_or_else(|err| panic!("Could not read {}: {err}", path.display()))
+}
+
+fn build(preset: &str, program: &[u8]) -> Chip8 {
+    Chip8Builder::from_preset(preset)
+        .expect("Known preset")
+        .with_program(program)
+        .build()
+        .expect("Test ROMs fit in memory")
+}
+
+//Runs until the program jumps to itself, which is how every test ROM finishes.
+fn run(chip_8: &mut Chip8) {
+    for _ in 0..MAX_FRAMES {
+        chip_8.dec_timers();
+        for _ in 0..TICKRATE {
+            chip_8
+                .execute_next()
+                .expect("Test ROMs only use valid instructions");
+            if is_idle_loop(chip_8) {
+                return;
+            }
+        }
+    }
+}
+
+fn is_idle_loop(chip_8: &Chip8) -> bool {
+    let pc = chip_8.get_pc() as usize;
+    let memory = chip_8.get_memory();
+    let word = u16::from_be_bytes([memory[pc], memory[pc + 1]]);
+    matches!(
+        Opcode::decode(word, chip_8.get_platform()),
+        Ok(Opcode::Jump { nnn }) if nnn as usize == pc
+    )
+}
+
+fn golden_path(name: &str, preset: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}-{preset}.txt"))
+}
+
+//Compares the display against tests/golden/<name>-<preset>.txt.
+fn check_golden(name: &str, preset: &str, chip_8: &Chip8) -> Result<(), String> {
+    let path = golden_path(name, preset);
+    let actual = screenshot::ascii(chip_8);
+    if env::var_os("CHIP8_BLESS").is_some() {
+        fs::write(&path, &actual).expect("Golden image written");
+        return Ok(());
+    }
+    let expected = fs::read_to_string(&path)
+        .map_err(|err| format!("Could not read {}: {err}", path.display()))?;
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "{name} under {preset} doesn't match {}, got:\n{actual}",
+            path.display()
+        ))
+    }
+}
+
+//Runs the ROM under every preset, setup can poke memory before it starts.
+fn check_rom(name: &str, program: &[u8], setup: impl Fn(&str, &mut Chip8)) {
+    let failures: Vec<String> = PRESETS
+        .iter()
+        .filter_map(|preset| {
+            let mut chip_8 = build(preset, program);
+            setup(preset, &mut chip_8);
+            run(&mut chip_8);
+            check_golden(name, preset, &chip_8).err()
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn corax_plus() {
+    check_rom("corax+", &rom("3-corax+.ch8"), |_, _| {});
+}
+
+#[test]
+fn flags() {
+    check_rom("flags", &rom("4-flags.ch8"), |_, _| {});
+}
+
+#[test]
+fn ibm_logo() {
+    check_rom("ibm-logo", &rom("chip8-test-suite.ch8"), |_, chip_8| {
+        chip_8.get_memory_mut()[SUITE_TEST_ADDR] = SUITE_IBM_LOGO;
+    });
+}
+
+#[test]
+fn quirks() {
+    check_rom("quirks", &rom("chip8-test-suite.ch8"), |preset, chip_8| {
+        //The suite only offers CHIP-8, SUPER-CHIP and XO-CHIP. Legacy and modern SCHIP
+        //differ only in half scrolling, which it doesn't test, so both check against SUPER-CHIP.
+        let platform = match preset {
+            "cosmac-vip" => 1,
+            "schip-legacy" | "schip-modern" => 2,
+            "xo-chip" => 3,
+            _ => unreachable!("Unknown preset {preset}"),
+        };
+        let memory = chip_8.get_memory_mut();
+        memory[SUITE_TEST_ADDR] = SUITE_QUIRKS;
+        memory[SUITE_PLATFORM_ADDR] = platform;
+    });
+}
+
+//The quirks goldens show each quirk's ON/OFF state, so they must differ exactly where
+//the presets differ in a quirk the suite tests.
+#[test]
+fn quirks_goldens_follow_presets() {
+    let tested_quirks = |preset: &str| Quirks {
+        half_scroll_quirk: false,
+        ..Chip8Builder::from_preset(preset)
+            .expect("Known preset")
+            .get_quirks()
+    };
+    let golden = |preset: &str| {
+        fs::read_to_string(golden_path("quirks", preset)).expect("Golden image exists")
+    };
+    for (i, left) in PRESETS.iter().enumerate() {
+        for right in &PRESETS[i + 1..] {
+            assert_eq!(
+                tested_quirks(left) == tested_quirks(right),
+                golden(left) == golden(right),
+                "quirks goldens for {left} and {right} disagree with their presets"
+            );
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//Single stepping through the debugger as the window frontend and gdb stub do.
+
+use chip8::{
+    debugger::{state_view, Debugger, StopReason},
+    Chip8, Chip8Builder,
+};
+
+fn build(preset: &str, program: &[u8]) -> Chip8 {
+    Chip8Builder::from_preset(preset)
+        .expect("Known preset")
+        .with_program(program)
+        .build()
+        .expect("Program fits in memory")
+}
+
+#[test]
+fn step_past_display_wait() {
+    //v0 := 5, sprite v0 v1 1, v0 += 1, then jump to itself.
+    let program = [0x60, 0x05, 0xD0, 0x11, 0x70, 0x01, 0x12, 0x06];
+    let mut chip_8 = build("cosmac-vip", &program);
+    let mut debugger = Debugger::new();
+    debugger.pause();
+    for pc in [0x202, 0x204, 0x206, 0x206] {
+        assert!(matches!(debugger.step(&mut chip_8), StopReason::Step));
+        assert_eq!(chip_8.get_pc(), pc);
+    }
+    assert_eq!(chip_8.get_v_regs()[0], 6);
+}
+
+#[test]
+fn step_past_frame_budget() {
+    //Jumps to itself, every step after the first spends a frame's whole budget.
+    let program = [0x12, 0x00];
+    let mut chip_8 = Chip8Builder::from_preset("schip-modern")
+        .expect("Known preset")
+        .with_tickrate(1)
+        .with_program(&program)
+        .build()
+        .expect("Program fits in memory");
+    chip_8.set_delay_reg(3);
+    let mut debugger = Debugger::new();
+    debugger.pause();
+    for _ in 0..3 {
+        debugger.step(&mut chip_8);
+    }
+    assert_eq!(chip_8.get_delay_reg(), 0);
+}
+
+#[test]
+fn step_out_of_main_stays_paused() {
+    //Jumps to itself without calling anything.
+    let program = [0x12, 0x00];
+    let mut chip_8 = build("cosmac-vip", &program);
+    let mut debugger = Debugger::new();
+    debugger.pause();
+    debugger.step_out(&chip_8);
+    assert!(debugger.is_paused());
+    assert!(debugger.run(&mut chip_8, 10).is_none());
+    assert_eq!(chip_8.get_cycles(), 0);
+}
+
+#[test]
+fn state_view_at_end_of_memory() {
+    let mut chip_8 = build("xo-chip", &[]);
+    chip_8.set_pc(0xFFFC);
+    assert!(state_view(&chip_8).contains("> fffc:"));
+}